@@ -33,8 +33,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Import
     println!("⏳ Importing...");
     match history.import_jsonl(jsonl_path).await {
-        Ok(count) => {
-            println!("✅ Successfully imported {} traces!", count);
+        Ok(report) => {
+            println!(
+                "✅ Successfully imported {} traces ({} already present)!",
+                report.imported(),
+                report.skipped
+            );
+            for error in &report.errors {
+                eprintln!("⚠️  Line {}: {}", error.line, error.message);
+            }
 
             // Show some stats
             let recent = history.recent(5).await?;
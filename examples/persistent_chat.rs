@@ -39,7 +39,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build();
 
     // Wrap with SmartAgent for automatic memory
-    let mut smart_agent = SmartAgent::new(agent, history.clone());
+    let mut smart_agent = SmartAgent::resume(agent, history.clone()).await?;
 
     // Show recent history if any
     let recent = history.recent(5).await?;
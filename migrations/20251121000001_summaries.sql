@@ -0,0 +1,49 @@
+-- Every summary ever generated for a session, newest one mirrored in sessions.summary
+CREATE TABLE IF NOT EXISTS summaries (
+    id TEXT PRIMARY KEY NOT NULL,
+    session_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    model TEXT,
+    first_trace_id TEXT,
+    last_trace_id TEXT,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
+
+-- Create FTS5 virtual table for summary search
+CREATE VIRTUAL TABLE IF NOT EXISTS summaries_fts USING fts5(
+    id UNINDEXED,
+    session_id UNINDEXED,
+    content,
+    content='summaries',
+    content_rowid='rowid'
+);
+
+-- Trigger to keep FTS index in sync on INSERT
+CREATE TRIGGER IF NOT EXISTS summaries_fts_insert AFTER INSERT ON summaries BEGIN
+    INSERT INTO summaries_fts(rowid, id, session_id, content)
+    VALUES (new.rowid, new.id, new.session_id, new.content);
+END;
+
+-- Trigger to keep FTS index in sync on UPDATE
+CREATE TRIGGER IF NOT EXISTS summaries_fts_update AFTER UPDATE ON summaries BEGIN
+    UPDATE summaries_fts SET
+        id = new.id,
+        session_id = new.session_id,
+        content = new.content
+    WHERE rowid = old.rowid;
+END;
+
+-- Trigger to keep FTS index in sync on DELETE
+CREATE TRIGGER IF NOT EXISTS summaries_fts_delete AFTER DELETE ON summaries BEGIN
+    DELETE FROM summaries_fts WHERE rowid = old.rowid;
+END;
+
+CREATE INDEX IF NOT EXISTS idx_summaries_session_id ON summaries(session_id, created_at);
+
+-- Keep the summaries generated before this table existed
+INSERT INTO summaries (id, session_id, content, last_trace_id, created_at)
+SELECT lower(hex(randomblob(16))), id, summary, summary_checkpoint,
+       strftime('%Y-%m-%dT%H:%M:%SZ', updated_at)
+FROM sessions
+WHERE summary IS NOT NULL;
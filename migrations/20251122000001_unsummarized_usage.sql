@@ -0,0 +1,13 @@
+-- Size of the conversation logged since the last summary, for size-based triggers
+ALTER TABLE sessions ADD COLUMN unsummarized_chars INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE sessions ADD COLUMN unsummarized_tokens INTEGER NOT NULL DEFAULT 0;
+
+-- Backfill from the traces after each session's checkpoint (4 characters per token)
+UPDATE sessions SET unsummarized_chars = (
+    SELECT COALESCE(SUM(LENGTH(t.content)), 0) FROM traces t
+    LEFT JOIN traces c ON c.id = sessions.summary_checkpoint
+    WHERE t.session_id = sessions.id
+      AND (c.id IS NULL
+           OR (julianday(t.created_at), t.rowid) > (julianday(c.created_at), c.rowid))
+);
+UPDATE sessions SET unsummarized_tokens = (unsummarized_chars + 3) / 4;
@@ -0,0 +1,2 @@
+-- SmartAgent turn count and configuration, restored when a session is resumed
+ALTER TABLE sessions ADD COLUMN agent_state TEXT;
@@ -0,0 +1,19 @@
+-- traces_fts is an external-content table: old index entries must be
+-- removed with the 'delete' command, which needs the old column values
+DROP TRIGGER IF EXISTS traces_fts_update;
+DROP TRIGGER IF EXISTS traces_fts_delete;
+
+CREATE TRIGGER traces_fts_update AFTER UPDATE ON traces BEGIN
+    INSERT INTO traces_fts(traces_fts, rowid, id, session_id, role, content, metadata)
+    VALUES ('delete', old.rowid, old.id, old.session_id, old.role, old.content, old.metadata);
+    INSERT INTO traces_fts(rowid, id, session_id, role, content, metadata)
+    VALUES (new.rowid, new.id, new.session_id, new.role, new.content, new.metadata);
+END;
+
+CREATE TRIGGER traces_fts_delete AFTER DELETE ON traces BEGIN
+    INSERT INTO traces_fts(traces_fts, rowid, id, session_id, role, content, metadata)
+    VALUES ('delete', old.rowid, old.id, old.session_id, old.role, old.content, old.metadata);
+END;
+
+-- Drop the stale entries left behind by the old triggers
+INSERT INTO traces_fts(traces_fts) VALUES ('rebuild');
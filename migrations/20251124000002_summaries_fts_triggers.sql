@@ -0,0 +1,19 @@
+-- summaries_fts is an external-content table: old index entries must be
+-- removed with the 'delete' command, which needs the old column values
+DROP TRIGGER IF EXISTS summaries_fts_update;
+DROP TRIGGER IF EXISTS summaries_fts_delete;
+
+CREATE TRIGGER summaries_fts_update AFTER UPDATE ON summaries BEGIN
+    INSERT INTO summaries_fts(summaries_fts, rowid, id, session_id, content)
+    VALUES ('delete', old.rowid, old.id, old.session_id, old.content);
+    INSERT INTO summaries_fts(rowid, id, session_id, content)
+    VALUES (new.rowid, new.id, new.session_id, new.content);
+END;
+
+CREATE TRIGGER summaries_fts_delete AFTER DELETE ON summaries BEGIN
+    INSERT INTO summaries_fts(summaries_fts, rowid, id, session_id, content)
+    VALUES ('delete', old.rowid, old.id, old.session_id, old.content);
+END;
+
+-- Drop the stale entries left behind by the old triggers
+INSERT INTO summaries_fts(summaries_fts) VALUES ('rebuild');
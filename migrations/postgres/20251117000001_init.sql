@@ -0,0 +1,28 @@
+-- Create sessions table
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    summary TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Create traces table with a generated tsvector for full-text search
+CREATE TABLE IF NOT EXISTS traces (
+    id TEXT PRIMARY KEY NOT NULL,
+    session_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    embedding TEXT,
+    search_vector TSVECTOR GENERATED ALWAYS AS (
+        to_tsvector('english', role || ' ' || content || ' ' || metadata::text)
+    ) STORED,
+    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
+
+-- Index for full-text search
+CREATE INDEX IF NOT EXISTS idx_traces_search_vector ON traces USING GIN (search_vector);
+
+-- Index for faster session lookups
+CREATE INDEX IF NOT EXISTS idx_traces_session_id ON traces(session_id);
+CREATE INDEX IF NOT EXISTS idx_traces_created_at ON traces(created_at);
@@ -0,0 +1,2 @@
+-- Last trace covered by the session summary, for incremental summarization
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS summary_checkpoint TEXT;
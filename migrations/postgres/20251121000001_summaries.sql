@@ -0,0 +1,25 @@
+-- Every summary ever generated for a session, newest one mirrored in sessions.summary
+CREATE TABLE IF NOT EXISTS summaries (
+    id TEXT PRIMARY KEY NOT NULL,
+    session_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    model TEXT,
+    first_trace_id TEXT,
+    last_trace_id TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    search_vector TSVECTOR GENERATED ALWAYS AS (
+        to_tsvector('english', content)
+    ) STORED,
+    FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+);
+
+-- Index for full-text search
+CREATE INDEX IF NOT EXISTS idx_summaries_search_vector ON summaries USING GIN (search_vector);
+
+CREATE INDEX IF NOT EXISTS idx_summaries_session_id ON summaries(session_id, created_at);
+
+-- Keep the summaries generated before this table existed
+INSERT INTO summaries (id, session_id, content, last_trace_id, created_at)
+SELECT gen_random_uuid()::text, id, summary, summary_checkpoint, updated_at
+FROM sessions
+WHERE summary IS NOT NULL;
@@ -0,0 +1,12 @@
+-- Size of the conversation logged since the last summary, for size-based triggers
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS unsummarized_chars BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS unsummarized_tokens BIGINT NOT NULL DEFAULT 0;
+
+-- Backfill from the traces after each session's checkpoint (4 characters per token)
+UPDATE sessions SET unsummarized_chars = (
+    SELECT COALESCE(SUM(LENGTH(t.content)), 0) FROM traces t
+    LEFT JOIN traces c ON c.id = sessions.summary_checkpoint
+    WHERE t.session_id = sessions.id
+      AND (c.id IS NULL OR (t.created_at, t.id) > (c.created_at, c.id))
+);
+UPDATE sessions SET unsummarized_tokens = (unsummarized_chars + 3) / 4;
@@ -0,0 +1,374 @@
+//! Token-budgeted assembly of the context injected into agent prompts
+
+use crate::{Role, SessionSummary, Trace};
+use rig::completion::Message;
+use std::sync::Arc;
+
+/// Smallest remainder (in tokens) worth filling with a truncated item
+const MIN_TRUNCATED_TOKENS: usize = 16;
+
+/// Marker appended to truncated text
+const ELLIPSIS: &str = "…";
+
+/// Heading of the injected session summary
+const SUMMARY_HEADER: &str = "Summary of this conversation so far:\n\n";
+
+/// Heading of the injected summaries of other sessions
+const RELATED_HEADER: &str = "Summaries of related past sessions:\n\n";
+
+/// Heading of the injected recalled traces
+const RECALL_HEADER: &str = "Relevant past experiences:\n\n";
+
+/// Estimates how many model tokens a piece of text uses
+pub trait TokenCounter: Send + Sync {
+    /// Count the tokens in `text`
+    fn count(&self, text: &str) -> usize;
+}
+
+/// Token estimate based on character count (default: 4 characters per token)
+#[derive(Debug, Clone)]
+pub struct CharHeuristic {
+    chars_per_token: usize,
+}
+
+impl CharHeuristic {
+    /// Estimate one token per `chars_per_token` characters
+    pub fn new(chars_per_token: usize) -> Self {
+        Self { chars_per_token: chars_per_token.max(1) }
+    }
+}
+
+impl Default for CharHeuristic {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+impl TokenCounter for CharHeuristic {
+    fn count(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(self.chars_per_token)
+    }
+}
+
+/// Fits session summary, recent turns and recalled traces into a token budget
+///
+/// Sections are filled in priority order: summary, then summaries of related
+/// sessions (sharing the summary's budget), then recent turns (newest
+/// first), then recalled traces (best match first). Each section
+/// may use up to its share of the budget plus whatever earlier sections left
+/// unused; recalled traces get everything that remains. An item that doesn't
+/// fit is truncated if enough room is left, otherwise it's dropped along
+/// with every lower-priority item in its section. Empty items are skipped.
+///
+/// # Example
+/// ```rust
+/// use agentsmith::{CharHeuristic, ContextBuilder};
+///
+/// let builder = ContextBuilder::new(2000)
+///     .with_token_counter(CharHeuristic::new(3))
+///     .with_summary_share(0.25)
+///     .with_recent_share(0.5);
+/// ```
+#[derive(Clone)]
+pub struct ContextBuilder {
+    max_tokens: usize,
+    summary_share: f64,
+    recent_share: f64,
+    counter: Arc<dyn TokenCounter>,
+}
+
+/// Context selected by a [`ContextBuilder`]
+#[derive(Debug, Clone, Default)]
+pub struct ContextWindow {
+    /// Session summary, possibly truncated
+    pub summary: Option<String>,
+
+    /// Summaries of related sessions, possibly truncated
+    pub related: Vec<SessionSummary>,
+
+    /// Recent turns in chronological order, possibly truncated
+    pub recent: Vec<Trace>,
+
+    /// Recalled traces in relevance order, possibly truncated
+    pub recalled: Vec<Trace>,
+
+    /// Estimated tokens used by the rendered context
+    pub tokens_used: usize,
+}
+
+impl Default for ContextBuilder {
+    fn default() -> Self {
+        Self::new(4000)
+    }
+}
+
+impl ContextBuilder {
+    /// Create a builder with a total budget of `max_tokens`
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens,
+            summary_share: 0.2,
+            recent_share: 0.4,
+            counter: Arc::new(CharHeuristic::default()),
+        }
+    }
+
+    /// Set the total token budget
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Use a custom token counter (default: [`CharHeuristic`])
+    pub fn with_token_counter(
+        mut self,
+        counter: impl TokenCounter + 'static,
+    ) -> Self {
+        self.counter = Arc::new(counter);
+        self
+    }
+
+    /// Set the fraction of the budget reserved for the summary (default: 0.2)
+    pub fn with_summary_share(mut self, share: f64) -> Self {
+        self.summary_share = share.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the fraction of the budget reserved for recent turns (default: 0.4)
+    pub fn with_recent_share(mut self, share: f64) -> Self {
+        self.recent_share = share.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Get the total token budget
+    pub fn max_tokens(&self) -> usize {
+        self.max_tokens
+    }
+
+    /// Get the token counter
+    pub fn token_counter(&self) -> &dyn TokenCounter {
+        self.counter.as_ref()
+    }
+
+    /// Select the context that fits the budget
+    ///
+    /// # Arguments
+    /// * `summary` - Session summary, if any
+    /// * `related` - Summaries of related sessions, best match first
+    /// * `recent` - Recent turns in chronological order
+    /// * `recalled` - Recalled traces, best match first
+    /// * `reserved` - Tokens already spoken for (e.g. the user's message)
+    pub fn build(
+        &self,
+        summary: Option<&str>,
+        related: &[SessionSummary],
+        recent: &[Trace],
+        recalled: &[Trace],
+        reserved: usize,
+    ) -> ContextWindow {
+        let total = self.max_tokens.saturating_sub(reserved);
+        let mut window = ContextWindow::default();
+
+        // 1. Session summary
+        let summary_cap = share_of(total, self.summary_share);
+        if let Some(summary) = summary.filter(|s| !s.trim().is_empty()) {
+            let overhead = self.counter.count(SUMMARY_HEADER);
+            if let Some(room) = summary_cap.checked_sub(overhead)
+                && let Some(text) = self.fit(summary, room)
+            {
+                window.tokens_used += overhead + self.counter.count(&text);
+                window.summary = Some(text);
+            }
+        }
+
+        // 2. Related sessions' summaries in what's left of the summary share
+        let mut related_cap = summary_cap.saturating_sub(window.tokens_used);
+        if !related.is_empty() {
+            related_cap =
+                related_cap.saturating_sub(self.counter.count(RELATED_HEADER));
+        }
+        let mut related_used = 0;
+        for summary in related {
+            if summary.content.trim().is_empty() {
+                continue;
+            }
+            let overhead = self.counter.count(&related_prefix(summary));
+            let Some(room) = related_cap.checked_sub(related_used + overhead)
+            else {
+                break;
+            };
+            let Some(content) = self.fit(&summary.content, room) else {
+                break;
+            };
+            related_used += overhead + self.counter.count(&content);
+            window.related.push(SessionSummary { content, ..summary.clone() });
+        }
+        if !window.related.is_empty() {
+            window.tokens_used +=
+                related_used + self.counter.count(RELATED_HEADER);
+        }
+
+        // 3. Recent turns, newest first, with the summaries' leftovers
+        let recent_cap = (summary_cap + share_of(total, self.recent_share))
+            .saturating_sub(window.tokens_used)
+            .min(total.saturating_sub(window.tokens_used));
+        let mut recent_used = 0;
+        for trace in recent.iter().rev() {
+            if trace.content.trim().is_empty() {
+                continue;
+            }
+            let overhead = self.counter.count(trace.role.as_str());
+            let Some(room) = recent_cap.checked_sub(recent_used + overhead)
+            else {
+                break;
+            };
+            let Some(content) = self.fit(&trace.content, room) else {
+                break;
+            };
+            recent_used += overhead + self.counter.count(&content);
+            window.recent.push(Trace { content, ..trace.clone() });
+        }
+        window.recent.reverse();
+        window.tokens_used += recent_used;
+
+        // 4. Recalled traces get whatever is left
+        let mut remaining = total.saturating_sub(window.tokens_used);
+        if !recalled.is_empty() {
+            remaining =
+                remaining.saturating_sub(self.counter.count(RECALL_HEADER));
+        }
+        let mut recall_used = 0;
+        for trace in recalled {
+            if trace.content.trim().is_empty() {
+                continue;
+            }
+            // Numbered by position in the rendered list
+            let i = window.recalled.len();
+            let overhead = self.counter.count(&recall_prefix(i, trace));
+            let Some(room) = remaining.checked_sub(recall_used + overhead)
+            else {
+                break;
+            };
+            let Some(content) = self.fit(&trace.content, room) else {
+                break;
+            };
+            recall_used += overhead + self.counter.count(&content);
+            window.recalled.push(Trace { content, ..trace.clone() });
+        }
+        if !window.recalled.is_empty() {
+            window.tokens_used +=
+                recall_used + self.counter.count(RECALL_HEADER);
+        }
+
+        window
+    }
+
+    /// Return `text` if it fits in `max` tokens, a truncated copy if enough
+    /// room is left, or `None`
+    fn fit(&self, text: &str, max: usize) -> Option<String> {
+        if self.counter.count(text) <= max {
+            return Some(text.to_string());
+        }
+        if max < MIN_TRUNCATED_TOKENS {
+            return None;
+        }
+
+        // Binary search for the longest prefix that fits with the ellipsis
+        let boundaries: Vec<usize> =
+            text.char_indices().map(|(i, _)| i).collect();
+        let (mut lo, mut hi) = (0, boundaries.len() - 1);
+        while lo < hi {
+            let mid = (lo + hi).div_ceil(2);
+            let candidate =
+                format!("{}{}", &text[..boundaries[mid]], ELLIPSIS);
+            if self.counter.count(&candidate) <= max {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+
+        (lo > 0).then(|| format!("{}{}", &text[..boundaries[lo]], ELLIPSIS))
+    }
+}
+
+impl ContextWindow {
+    /// Whether nothing was selected
+    pub fn is_empty(&self) -> bool {
+        self.summary.is_none()
+            && self.related.is_empty()
+            && self.recent.is_empty()
+            && self.recalled.is_empty()
+    }
+
+    /// Render as chat history: system messages first, then recent turns
+    pub fn to_messages(&self) -> Vec<Message> {
+        let mut messages = Vec::new();
+
+        if let Some(summary) = &self.summary {
+            messages.push(Message {
+                role: Role::System.to_string(),
+                content: format!("{}{}", SUMMARY_HEADER, summary),
+            });
+        }
+
+        if !self.related.is_empty() {
+            let mut related_context = String::from(RELATED_HEADER);
+            for summary in &self.related {
+                related_context.push_str(&related_prefix(summary));
+                related_context.push_str(&summary.content);
+                related_context.push('\n');
+            }
+
+            messages.push(Message {
+                role: Role::System.to_string(),
+                content: related_context,
+            });
+        }
+
+        if !self.recalled.is_empty() {
+            let mut recall_context = String::from(RECALL_HEADER);
+            for (i, trace) in self.recalled.iter().enumerate() {
+                recall_context.push_str(&recall_prefix(i, trace));
+                recall_context.push_str(&trace.content);
+                recall_context.push('\n');
+            }
+
+            messages.push(Message {
+                role: Role::System.to_string(),
+                content: recall_context,
+            });
+        }
+
+        messages.extend(self.recent.iter().map(|trace| Message {
+            role: trace.role.to_string(),
+            content: trace.content.clone(),
+        }));
+
+        messages
+    }
+}
+
+/// Numbered, timestamped prefix for a recalled trace line
+fn recall_prefix(i: usize, trace: &Trace) -> String {
+    format!(
+        "{}. [{}] {}: ",
+        i + 1,
+        trace.created_at.format("%Y-%m-%d %H:%M"),
+        trace.role
+    )
+}
+
+/// Dated prefix for a related session's summary line
+fn related_prefix(summary: &SessionSummary) -> String {
+    format!(
+        "- [{}] session {}: ",
+        summary.created_at.format("%Y-%m-%d"),
+        summary.session_id
+    )
+}
+
+/// Tokens available for a section given its share of the budget
+fn share_of(total: usize, share: f64) -> usize {
+    (total as f64 * share) as usize
+}
@@ -0,0 +1,124 @@
+//! Pluggable text embedders for semantic search over traces
+
+use crate::{Error, Result};
+use rig::embeddings::EmbeddingModel;
+use std::{future::Future, pin::Pin};
+
+/// Boxed future returned by [`Embedder::embed`]
+pub type EmbedFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<f64>>> + Send + 'a>>;
+
+/// Turns text into a fixed-size vector for similarity search
+///
+/// Implementations must return vectors of the same length for every input,
+/// otherwise stored embeddings can't be compared.
+pub trait Embedder: Send + Sync {
+    /// Embed a single piece of text
+    fn embed<'a>(&'a self, text: &'a str) -> EmbedFuture<'a>;
+}
+
+/// Deterministic, offline embedder using hashed bag-of-words
+///
+/// Each lowercased alphanumeric token is hashed (FNV-1a) into one of `dims`
+/// buckets and the resulting counts are L2-normalized. It has no notion of
+/// synonyms, but needs no model and gives stable results for tests.
+#[derive(Debug, Clone)]
+pub struct HashEmbedder {
+    dims: usize,
+}
+
+impl HashEmbedder {
+    /// Create a hashed bag-of-words embedder with `dims` dimensions
+    pub fn new(dims: usize) -> Self {
+        Self { dims: dims.max(1) }
+    }
+
+    /// Compute the embedding synchronously
+    pub fn embed_sync(&self, text: &str) -> Vec<f64> {
+        let mut vec = vec![0.0; self.dims];
+
+        for token in tokenize(text) {
+            let bucket = fnv1a(token.bytes()) as usize % self.dims;
+            vec[bucket] += 1.0;
+        }
+
+        normalize(&mut vec);
+        vec
+    }
+}
+
+impl Default for HashEmbedder {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+impl Embedder for HashEmbedder {
+    fn embed<'a>(&'a self, text: &'a str) -> EmbedFuture<'a> {
+        Box::pin(async move { Ok(self.embed_sync(text)) })
+    }
+}
+
+/// Adapter that uses a Rig [`EmbeddingModel`] as an [`Embedder`]
+#[derive(Clone)]
+pub struct RigEmbedder<M: EmbeddingModel> {
+    model: M,
+}
+
+impl<M: EmbeddingModel> RigEmbedder<M> {
+    /// Wrap a Rig embedding model
+    pub fn new(model: M) -> Self {
+        Self { model }
+    }
+}
+
+impl<M: EmbeddingModel> Embedder for RigEmbedder<M> {
+    fn embed<'a>(&'a self, text: &'a str) -> EmbedFuture<'a> {
+        Box::pin(async move {
+            let embedding = self
+                .model
+                .embed_document(text)
+                .await
+                .map_err(|e| Error::Rig(e.to_string()))?;
+            Ok(embedding.vec)
+        })
+    }
+}
+
+/// Cosine similarity between two vectors (0.0 if lengths differ or either is zero)
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 { 0.0 } else { dot / (norm_a * norm_b) }
+}
+
+/// Split text into lowercased alphanumeric tokens
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+}
+
+/// 64-bit FNV-1a hash, stable across platforms and Rust versions
+pub(crate) fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Scale a vector to unit length in place
+fn normalize(vec: &mut [f64]) {
+    let norm = vec.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        vec.iter_mut().for_each(|x| *x /= norm);
+    }
+}
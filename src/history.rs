@@ -1,20 +1,35 @@
 //! Core AgentHistory implementation for persistent agent memory
 
-use crate::{Error, Result, Trace};
-use chrono::Utc;
+use crate::{
+    CharHeuristic, Error, ExportFilter, HistoryStore, ImportError,
+    ImportOptions, ImportReport, ImportStatus, Result, Role, SearchQuery,
+    Session, SessionScope, SessionSummary, SqliteStore, SummaryConfig,
+    TokenCounter, Trace, TranscriptOptions,
+    embedding::{Embedder, cosine_similarity},
+    import, summary,
+};
 use rig::{
     agent::Agent,
-    completion::{Chat, CompletionModel, Message},
+    completion::{CompletionModel, Message},
 };
 use serde_json::Value;
-use sqlx::{Row, sqlite::SqlitePool};
-use std::{collections::HashMap, path::Path};
+use std::{collections::HashMap, path::Path, sync::Arc};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+    BufWriter,
+};
+
+/// Traces fetched from the store per round trip while exporting
+const EXPORT_PAGE_SIZE: usize = 500;
 
 /// Persistent history storage for agent interactions
 #[derive(Clone)]
 pub struct AgentHistory {
-    pool: SqlitePool,
+    store: Arc<dyn HistoryStore>,
     session_id: String,
+    embedder: Option<Arc<dyn Embedder>>,
+    summary_config: SummaryConfig,
+    counter: Arc<dyn TokenCounter>,
 }
 
 impl AgentHistory {
@@ -36,31 +51,96 @@ impl AgentHistory {
         path: impl AsRef<Path>,
         session_id: Option<&str>,
     ) -> Result<Self> {
-        let path_str = path.as_ref().to_string_lossy().to_string();
-        let db_url = if path_str == ":memory:" {
-            "sqlite::memory:".to_string()
-        } else {
-            format!("sqlite://{}", path_str)
-        };
-
-        let pool = SqlitePool::connect(&db_url).await?;
-
-        // Run migrations
-        sqlx::migrate!("./migrations").run(&pool).await?;
+        let store = SqliteStore::new(path).await?;
+        Self::with_store(store, session_id).await
+    }
 
+    /// Create a new AgentHistory instance on top of any storage backend
+    ///
+    /// # Arguments
+    /// * `store` - Storage backend (e.g. [`MemoryStore`](crate::MemoryStore))
+    /// * `session_id` - Optional session identifier (generates UUID if None)
+    ///
+    /// # Example
+    /// ```rust
+    /// # use agentsmith::{AgentHistory, MemoryStore};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let history =
+    ///     AgentHistory::with_store(MemoryStore::new(), Some("test")).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn with_store(
+        store: impl HistoryStore + 'static,
+        session_id: Option<&str>,
+    ) -> Result<Self> {
         let session_id = session_id
             .map(String::from)
             .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
 
+        let history = Self {
+            store: Arc::new(store),
+            session_id,
+            embedder: None,
+            summary_config: SummaryConfig::default(),
+            counter: Arc::new(CharHeuristic::default()),
+        };
+
         // Create session if it doesn't exist
-        sqlx::query(
-            "INSERT OR IGNORE INTO sessions (id, updated_at) VALUES (?, datetime('now'))",
-        )
-        .bind(&session_id)
-        .execute(&pool)
-        .await?;
+        history.store.ensure_session(&history.session_id).await?;
+
+        Ok(history)
+    }
+
+    /// Embed every newly logged turn with `embedder` for semantic search
+    ///
+    /// Turns whose embedding fails are stored without one and logged as a
+    /// warning; [`AgentHistory::embed_missing`] embeds them later.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, HashEmbedder};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let history = AgentHistory::new("agent.db", Some("session-1"))
+    ///     .await?
+    ///     .with_embedder(HashEmbedder::default());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_embedder(mut self, embedder: impl Embedder + 'static) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
 
-        Ok(Self { pool, session_id })
+    /// Set how sessions are summarized
+    pub fn with_summary_config(mut self, config: SummaryConfig) -> Self {
+        self.summary_config = config;
+        self
+    }
+
+    /// Estimate tokens logged since the last summary with a custom counter
+    /// (default: [`CharHeuristic`])
+    pub fn with_token_counter(
+        mut self,
+        counter: impl TokenCounter + 'static,
+    ) -> Self {
+        self.counter = Arc::new(counter);
+        self
+    }
+
+    /// Get the summarization settings
+    pub fn summary_config(&self) -> &SummaryConfig {
+        &self.summary_config
+    }
+
+    /// Get the configured embedder, if any
+    pub fn embedder(&self) -> Option<&dyn Embedder> {
+        self.embedder.as_deref()
+    }
+
+    /// Get the storage backend
+    pub fn store(&self) -> &dyn HistoryStore {
+        self.store.as_ref()
     }
 
     /// Get the current session ID
@@ -68,6 +148,55 @@ impl AgentHistory {
         &self.session_id
     }
 
+    /// List all sessions, most recently updated first
+    pub async fn list_sessions(&self) -> Result<Vec<Session>> {
+        self.store.list_sessions().await
+    }
+
+    /// Get a single session by ID
+    pub async fn get_session(
+        &self,
+        session_id: &str,
+    ) -> Result<Option<Session>> {
+        self.store.get_session(session_id).await
+    }
+
+    /// Delete a session and all of its traces
+    ///
+    /// Returns `false` if the session did not exist. Deleting the current
+    /// session is allowed; it will be recreated on the next logged turn.
+    pub async fn delete_session(&self, session_id: &str) -> Result<bool> {
+        self.store.delete_session(session_id).await
+    }
+
+    /// Rename a session, moving all of its traces to the new ID
+    ///
+    /// If the renamed session is the current one, this instance follows it.
+    pub async fn rename_session(
+        &mut self,
+        old_id: &str,
+        new_id: &str,
+    ) -> Result<()> {
+        if old_id == new_id {
+            return Ok(());
+        }
+
+        self.store.rename_session(old_id, new_id).await?;
+
+        if self.session_id == old_id {
+            self.session_id = new_id.to_string();
+        }
+
+        Ok(())
+    }
+
+    /// Switch this instance to another session, creating it if needed
+    pub async fn switch_session(&mut self, session_id: &str) -> Result<()> {
+        self.store.ensure_session(session_id).await?;
+        self.session_id = session_id.to_string();
+        Ok(())
+    }
+
     /// Log a single agent turn (message) to the history
     ///
     /// # Arguments
@@ -78,52 +207,40 @@ impl AgentHistory {
         message: &Message,
         metadata: HashMap<String, Value>,
     ) -> Result<Trace> {
-        let trace = Trace::new(
+        let mut trace = Trace::new(
             self.session_id.clone(),
             message.role.clone(),
             message.content.clone(),
         )
         .with_metadata(metadata);
 
-        self.log_trace(&trace).await?;
-
-        // Update session timestamp
-        sqlx::query(
-            "UPDATE sessions SET updated_at = datetime('now') WHERE id = ?",
-        )
-        .bind(&self.session_id)
-        .execute(&self.pool)
-        .await?;
-
-        Ok(trace)
-    }
+        // A failed embedding shouldn't lose the turn; embed_missing can
+        // fill it in later
+        if let Some(embedder) = &self.embedder {
+            match embedder.embed(&trace.content).await {
+                Ok(vec) => {
+                    trace.embedding = Some(serde_json::to_string(&vec)?)
+                }
+                Err(e) => tracing::warn!("Failed to embed trace: {}", e),
+            }
+        }
 
-    /// Log a trace directly
-    async fn log_trace(&self, trace: &Trace) -> Result<()> {
-        let metadata_json = serde_json::to_string(&trace.metadata)?;
-        let created_at = trace.created_at.to_rfc3339();
+        // Update session timestamp (recreating the session if it was deleted)
+        self.store.touch_session(&self.session_id).await?;
 
-        sqlx::query(
-            r#"
-            INSERT INTO traces (id, session_id, role, content, metadata, created_at, embedding)
-            VALUES (?, ?, ?, ?, ?, ?, ?)
-            "#,
-        )
-        .bind(&trace.id)
-        .bind(&trace.session_id)
-        .bind(&trace.role)
-        .bind(&trace.content)
-        .bind(&metadata_json)
-        .bind(&created_at)
-        .bind(&trace.embedding)
-        .execute(&self.pool)
-        .await?;
+        let (chars, tokens) = self.usage(std::slice::from_ref(&trace));
+        self.store.insert_trace(&trace, chars, tokens).await?;
 
-        Ok(())
+        Ok(trace)
     }
 
     /// Search traces using FTS5 fuzzy search (Atuin-style)
     ///
+    /// The query is treated as plain text: it is tokenized and escaped with
+    /// [`build_fts_query`](crate::build_fts_query), so punctuation such as
+    /// `C++` or `what's "json"?` is safe. Use [`AgentHistory::search_raw`]
+    /// to pass FTS5 syntax through unchanged.
+    ///
     /// # Arguments
     /// * `query` - Search query string
     /// * `limit` - Maximum number of results
@@ -134,165 +251,643 @@ impl AgentHistory {
         limit: usize,
         success_only: bool,
     ) -> Result<Vec<Trace>> {
-        // Build FTS5 query - use MATCH for full-text search
-        let fts_query = if query.is_empty() {
+        if query.trim().is_empty() {
             // If empty query, return recent traces
             return self.recent(limit).await;
-        } else {
-            query.to_string()
-        };
+        }
 
-        let sql = r#"
-            SELECT t.id, t.session_id, t.role, t.content, t.metadata, t.created_at, t.embedding
-            FROM traces t
-            JOIN traces_fts fts ON t.rowid = fts.rowid
-            WHERE traces_fts MATCH ?
-            ORDER BY rank, t.created_at DESC
-            LIMIT ?
-            "#;
-
-        let rows = sqlx::query(sql)
-            .bind(&fts_query)
-            .bind(limit as i64)
-            .fetch_all(&self.pool)
-            .await?;
+        self.query(
+            &SearchQuery::new(query).limit(limit).success_only(success_only),
+        )
+        .await
+    }
+
+    /// Search traces with a raw FTS5 MATCH expression
+    ///
+    /// Supports the full FTS5 query syntax (`AND`, `OR`, `NOT`, `NEAR`,
+    /// phrases, prefixes and column filters); invalid syntax is reported as
+    /// a database error.
+    ///
+    /// # Arguments
+    /// * `fts_query` - FTS5 query expression
+    /// * `limit` - Maximum number of results
+    /// * `success_only` - Only return traces marked as successful
+    pub async fn search_raw(
+        &self,
+        fts_query: &str,
+        limit: usize,
+        success_only: bool,
+    ) -> Result<Vec<Trace>> {
+        self.query(
+            &SearchQuery::raw(fts_query)
+                .limit(limit)
+                .success_only(success_only),
+        )
+        .await
+    }
+
+    /// Run a structured [`SearchQuery`], with every filter applied by the store
+    pub async fn query(&self, query: &SearchQuery) -> Result<Vec<Trace>> {
+        if query.scope == SessionScope::Current {
+            let resolved = query
+                .clone()
+                .scope(SessionScope::Only(vec![self.session_id.clone()]));
+            return self.store.query(&resolved).await;
+        }
+
+        self.store.query(query).await
+    }
+
+    /// Search traces by embedding similarity to `query`
+    ///
+    /// Requires an embedder (see [`AgentHistory::with_embedder`]). Searches
+    /// all sessions and returns up to `k` traces ordered by descending cosine
+    /// similarity; traces without an embedding, with a vector of a different
+    /// size, or with no similarity at all are skipped.
+    pub async fn semantic_search(
+        &self,
+        query: &str,
+        k: usize,
+    ) -> Result<Vec<Trace>> {
+        Ok(self
+            .semantic_search_scored(query, k)
+            .await?
+            .into_iter()
+            .map(|(trace, _)| trace)
+            .collect())
+    }
 
-        let mut traces = Vec::new();
-        for row in rows {
-            let trace = self.row_to_trace(row)?;
-            if !success_only || trace.is_success() {
-                traces.push(trace);
+    /// Like [`AgentHistory::semantic_search`], but also returns each score
+    pub async fn semantic_search_scored(
+        &self,
+        query: &str,
+        k: usize,
+    ) -> Result<Vec<(Trace, f64)>> {
+        let embedder = self.embedder.as_ref().ok_or_else(|| {
+            Error::Other("No embedder configured".to_string())
+        })?;
+        let query_vec = embedder.embed(query).await?;
+
+        let mut scored = Vec::new();
+        for trace in self.store.embedded_traces().await? {
+            let Some(vec) = trace.embedding_vector() else {
+                continue;
+            };
+
+            let score = cosine_similarity(&query_vec, &vec);
+            if score > 0.0 {
+                scored.push((trace, score));
             }
         }
 
-        Ok(traces)
+        scored.sort_by(|a, b| {
+            b.1.total_cmp(&a.1).then(b.0.created_at.cmp(&a.0.created_at))
+        });
+        scored.truncate(k);
+
+        Ok(scored)
     }
 
-    /// Get the N most recent traces
-    pub async fn recent(&self, n: usize) -> Result<Vec<Trace>> {
-        let rows = sqlx::query(
-            r#"
-            SELECT id, session_id, role, content, metadata, created_at, embedding
-            FROM traces
-            WHERE session_id = ?
-            ORDER BY created_at DESC
-            LIMIT ?
-            "#,
-        )
-        .bind(&self.session_id)
-        .bind(n as i64)
-        .fetch_all(&self.pool)
-        .await?;
-
-        let mut traces = Vec::new();
-        for row in rows {
-            traces.push(self.row_to_trace(row)?);
+    /// Compute embeddings for traces stored without one (e.g. imported logs)
+    ///
+    /// Returns the number of traces updated.
+    pub async fn embed_missing(&self) -> Result<usize> {
+        let embedder = self.embedder.as_ref().ok_or_else(|| {
+            Error::Other("No embedder configured".to_string())
+        })?;
+
+        let mut count = 0;
+        for trace in self.store.unembedded_traces().await? {
+            let vec = embedder.embed(&trace.content).await?;
+            self.store
+                .set_embedding(&trace.id, &serde_json::to_string(&vec)?)
+                .await?;
+            count += 1;
         }
 
-        // Reverse to get chronological order
-        traces.reverse();
-        Ok(traces)
+        Ok(count)
+    }
+
+    /// Get the N most recent traces
+    pub async fn recent(&self, n: usize) -> Result<Vec<Trace>> {
+        self.store.recent(&self.session_id, n).await
     }
 
     /// Get recent traces as Rig Messages for context injection
+    ///
+    /// Error traces among the `n` most recent are left out, since they
+    /// aren't chat turns a model would accept.
     pub async fn recent_messages(&self, n: usize) -> Result<Vec<Message>> {
         let traces = self.recent(n).await?;
-        Ok(traces.into_iter().map(trace_to_message).collect())
+        Ok(traces
+            .into_iter()
+            .filter(|trace| trace.role != Role::Error)
+            .map(trace_to_message)
+            .collect())
     }
 
     /// Generate a summary of the current session using an agent
+    ///
+    /// Only traces logged since the last summary are sent, together with
+    /// that summary; the ID of the last trace covered is stored as the new
+    /// checkpoint. Returns the existing summary without calling the agent if
+    /// nothing was logged since. See [`SummaryConfig`] for how long
+    /// transcripts are split.
     pub async fn summarize_session<M: CompletionModel>(
         &self,
         summarizer: &Agent<M>,
     ) -> Result<String> {
-        // Get all traces from this session
-        let rows = sqlx::query(
-            r#"
-            SELECT id, session_id, role, content, metadata, created_at, embedding
-            FROM traces
-            WHERE session_id = ?
-            ORDER BY created_at ASC
-            "#,
-        )
-        .bind(&self.session_id)
-        .fetch_all(&self.pool)
-        .await?;
-
-        let mut traces = Vec::new();
-        for row in rows {
-            traces.push(self.row_to_trace(row)?);
+        // Summaries stored before checkpoints existed are rebuilt from scratch
+        let (previous, checkpoint) =
+            match self.store.get_session(&self.session_id).await? {
+                Some(Session {
+                    summary: Some(summary),
+                    summary_checkpoint: Some(checkpoint),
+                    ..
+                }) => (Some(summary), Some(checkpoint)),
+                _ => (None, None),
+            };
+
+        let traces = self
+            .store
+            .traces_after(&self.session_id, checkpoint.as_deref())
+            .await?;
+        if let (Some(previous), true) = (&previous, traces.is_empty()) {
+            return Ok(previous.clone());
         }
 
-        // Build conversation history for summarization
-        let mut conversation = String::new();
-        for trace in &traces {
-            conversation
-                .push_str(&format!("{}: {}\n", trace.role, trace.content));
+        let config = &self.summary_config;
+        let session_id = self.session_id.as_str();
+        let chunks = config.transcript_chunks(&traces);
+        let summary = match previous {
+            // Fold new turns into the running summary
+            Some(mut summary) => {
+                for chunk in &chunks {
+                    let prompt =
+                        config.update_prompt(session_id, &summary, chunk);
+                    summary = config.parse_reply(
+                        summary::ask(summarizer, &prompt).await?,
+                    )?;
+                }
+                summary
+            }
+            None if chunks.len() == 1 => {
+                let prompt = config.initial_prompt(session_id, &chunks[0]);
+                config.parse_reply(summary::ask(summarizer, &prompt).await?)?
+            }
+            // Map-reduce: summarize each chunk, then combine the parts
+            None => {
+                let mut partials = Vec::with_capacity(chunks.len());
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let prompt = config.chunk_prompt(
+                        session_id,
+                        i + 1,
+                        chunks.len(),
+                        chunk,
+                    );
+                    partials.push(summary::ask(summarizer, &prompt).await?);
+                }
+                let prompt = config.combine_prompt(session_id, &partials);
+                config.parse_reply(summary::ask(summarizer, &prompt).await?)?
+            }
+        };
+
+        let mut record = SessionSummary::new(self.session_id.clone(), summary);
+        record.model = self.summary_config.model_name.clone();
+        record.first_trace_id = traces.first().map(|t| t.id.clone());
+        record.last_trace_id = traces.last().map(|t| t.id.clone());
+        self.store.add_summary(&record).await?;
+
+        // Turns logged while the summary was generated stay unsummarized
+        let (chars, tokens) = self.usage(&traces);
+        self.store.add_unsummarized(&self.session_id, -chars, -tokens).await?;
+
+        Ok(record.content)
+    }
+
+    /// Characters and estimated tokens in the content of `traces`
+    fn usage(&self, traces: &[Trace]) -> (i64, i64) {
+        traces.iter().fold((0, 0), |(chars, tokens), trace| {
+            (
+                chars + trace.content.chars().count() as i64,
+                tokens + self.counter.count(&trace.content) as i64,
+            )
+        })
+    }
+
+    /// List every summary generated for a session, oldest first
+    pub async fn list_summaries(
+        &self,
+        session_id: &str,
+    ) -> Result<Vec<SessionSummary>> {
+        self.store.list_summaries(session_id).await
+    }
+
+    /// Get the newest summary of a session
+    pub async fn latest_summary(
+        &self,
+        session_id: &str,
+    ) -> Result<Option<SessionSummary>> {
+        self.store.latest_summary(session_id).await
+    }
+
+    /// Search the summaries of all sessions, best match first
+    ///
+    /// Uses the same query handling as [`AgentHistory::search`], so the two
+    /// can be combined to recall both individual turns and whole sessions.
+    pub async fn search_summaries(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SessionSummary>> {
+        self.store.search_summaries(query, limit).await
+    }
+
+    /// Render a session as a Markdown or HTML transcript
+    ///
+    /// Messages are listed oldest first under a heading with their role and,
+    /// optionally, timestamp and metadata badges such as `duration_ms` and
+    /// `success`. The session's latest summary, if any, is shown on top.
+    ///
+    /// # Arguments
+    /// * `session_id` - Session to render
+    /// * `options` - Format and what to include
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, TranscriptFormat, TranscriptOptions};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let history = AgentHistory::new("agent.db", None).await?;
+    ///
+    /// let options = TranscriptOptions::default()
+    ///     .with_format(TranscriptFormat::Html)
+    ///     .with_redacted("user_id")
+    ///     .with_collapsed_system(true);
+    /// let html = history.render_session("incident-42", &options).await?;
+    /// std::fs::write("incident-42.html", html)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn render_session(
+        &self,
+        session_id: &str,
+        options: &TranscriptOptions,
+    ) -> Result<String> {
+        if self.store.get_session(session_id).await?.is_none() {
+            return Err(Error::Other(format!(
+                "Session not found: {}",
+                session_id
+            )));
         }
 
-        // Ask the agent to summarize
-        let summary_prompt = format!(
-            "Please provide a concise summary of the following conversation:\n\n{}",
-            conversation
-        );
-
-        let summary = summarizer
-            .chat(&summary_prompt, vec![])
-            .await
-            .map_err(|e| Error::Rig(e.to_string()))?;
-
-        // Store summary in sessions table
-        sqlx::query("UPDATE sessions SET summary = ?, updated_at = datetime('now') WHERE id = ?")
-            .bind(&summary)
-            .bind(&self.session_id)
-            .execute(&self.pool)
-            .await?;
+        let traces = self.store.session_traces(session_id).await?;
+        let summary = if options.summary {
+            self.store.latest_summary(session_id).await?
+        } else {
+            None
+        };
 
-        Ok(summary)
+        Ok(options.render(session_id, &traces, summary.as_ref()))
     }
 
     /// Import traces from a JSONL file (for migrating old logs)
-    pub async fn import_jsonl(&self, path: &str) -> Result<usize> {
-        let content = tokio::fs::read_to_string(path).await?;
-        let mut count = 0;
+    ///
+    /// Uses the default [`ImportOptions`]: traces already stored are
+    /// skipped, so importing the same file twice is harmless.
+    pub async fn import_jsonl(&self, path: &str) -> Result<ImportReport> {
+        self.import_jsonl_with(path, &ImportOptions::default()).await
+    }
+
+    /// Import traces from a JSONL file
+    ///
+    /// The file is streamed and written in transactions of
+    /// `options.batch_size` traces. Sessions are created as needed. Lines
+    /// that aren't valid traces are listed in the report's `errors` and
+    /// don't stop the import.
+    ///
+    /// # Arguments
+    /// * `path` - JSONL file with one [`Trace`] per line
+    /// * `options` - Batch size and duplicate handling
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, DuplicateStrategy, ImportOptions};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let history = AgentHistory::new("agent.db", None).await?;
+    ///
+    /// let options =
+    ///     ImportOptions::default().with_duplicates(DuplicateStrategy::Upsert);
+    /// let report = history.import_jsonl_with("backup.jsonl", &options).await?;
+    /// for error in &report.errors {
+    ///     eprintln!("line {}: {}", error.line, error.message);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn import_jsonl_with(
+        &self,
+        path: &str,
+        options: &ImportOptions,
+    ) -> Result<ImportReport> {
+        let file = tokio::fs::File::open(path).await?;
+        self.read_jsonl(BufReader::new(file), options).await
+    }
+
+    /// Import JSONL traces from any async reader
+    ///
+    /// The streaming counterpart of [`AgentHistory::import_jsonl_with`].
+    /// With [`DuplicateStrategy::Fail`], the first duplicate stops the
+    /// import with an error naming its line; traces before it are kept.
+    ///
+    /// [`DuplicateStrategy::Fail`]: crate::DuplicateStrategy::Fail
+    pub async fn read_jsonl<R: AsyncBufRead + Unpin>(
+        &self,
+        reader: R,
+        options: &ImportOptions,
+    ) -> Result<ImportReport> {
+        self.read_lines(reader, options, |line| {
+            serde_json::from_str::<Trace>(line)
+                .map(|trace| vec![trace])
+                .map_err(|e| e.to_string())
+        })
+        .await
+    }
+
+    /// Import a ChatGPT data export (`conversations.json`)
+    ///
+    /// Each conversation becomes a session named after its ID. Only the
+    /// branch the user last saw is imported: edited and regenerated
+    /// messages on other branches are left out. Traces keep ChatGPT's
+    /// message IDs and timestamps; the conversation title, model and tool
+    /// recipients are stored as metadata (`conversation_title`, `model`,
+    /// `recipient`) with `source` set to `"chatgpt"`.
+    ///
+    /// The export is streamed: conversations are parsed and stored one
+    /// batch at a time, so even years of history don't have to fit in
+    /// memory. Conversations that can't be mapped are listed in the report's
+    /// `errors` by position and don't stop the import; a file that isn't
+    /// valid JSON stops it with an error, keeping the conversations before
+    /// the fault.
+    ///
+    /// # Arguments
+    /// * `path` - The `conversations.json` file from the export
+    /// * `options` - Batch size and duplicate handling
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, ImportOptions};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let history = AgentHistory::new("agent.db", None).await?;
+    ///
+    /// let report = history
+    ///     .import_chatgpt("conversations.json", &ImportOptions::default())
+    ///     .await?;
+    /// println!("{} conversations, {} messages", report.lines, report.imported());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn import_chatgpt(
+        &self,
+        path: &str,
+        options: &ImportOptions,
+    ) -> Result<ImportReport> {
+        let file = tokio::fs::File::open(path).await?.into_std().await;
+
+        // serde_json reads synchronously, so parse on a blocking thread and
+        // hand conversations over one at a time
+        let (sender, mut conversations) = tokio::sync::mpsc::channel(16);
+        let reader = tokio::task::spawn_blocking(move || {
+            import::read_chatgpt_export(file, sender)
+        });
+
+        let mut report = ImportReport::default();
+        let mut batch: Vec<(usize, Trace)> = Vec::new();
+
+        while let Some(conversation) = conversations.recv().await {
+            report.lines += 1;
+            let position = report.lines;
+            match import::chatgpt_traces(&conversation) {
+                Ok(traces) => batch
+                    .extend(traces.into_iter().map(|trace| (position, trace))),
+                Err(message) => {
+                    report.errors.push(ImportError { line: position, message })
+                }
+            }
 
-        for line in content.lines() {
+            if batch.len() >= options.batch_size {
+                self.import_batch(&mut batch, options, &mut report).await?;
+            }
+        }
+        self.import_batch(&mut batch, options, &mut report).await?;
+
+        reader.await.map_err(|e| {
+            Error::Other(format!("ChatGPT export reader failed: {}", e))
+        })??;
+        Ok(report)
+    }
+
+    /// Import an OpenAI chat-completions request log
+    ///
+    /// The log is JSONL with one API call per line: either the request body,
+    /// or an object with `request` and `response` fields. Each call's
+    /// messages and first reply choice become traces, grouped into sessions
+    /// by the line's `session_id` (or `conversation_id`) field, else by
+    /// completion ID. Messages that earlier calls of the same session
+    /// already sent are recognized as duplicates, so keep the default
+    /// [`DuplicateStrategy::Skip`] for logs of multi-turn conversations.
+    ///
+    /// Tool calls are stored in `tool_calls` metadata, and replies carry
+    /// `model`, `tokens_used` and `finish_reason`. Traces have `source` set
+    /// to `"openai"`.
+    ///
+    /// [`DuplicateStrategy::Skip`]: crate::DuplicateStrategy::Skip
+    ///
+    /// # Arguments
+    /// * `path` - JSONL file with one logged call per line
+    /// * `options` - Batch size and duplicate handling
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, ImportOptions};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let history = AgentHistory::new("agent.db", None).await?;
+    ///
+    /// let report = history
+    ///     .import_openai_log("requests.jsonl", &ImportOptions::default())
+    ///     .await?;
+    /// println!("Imported {} messages", report.imported());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn import_openai_log(
+        &self,
+        path: &str,
+        options: &ImportOptions,
+    ) -> Result<ImportReport> {
+        let file = tokio::fs::File::open(path).await?;
+        self.read_lines(BufReader::new(file), options, |line| {
+            let record: Value =
+                serde_json::from_str(line).map_err(|e| e.to_string())?;
+            import::openai_log_traces(&record)
+        })
+        .await
+    }
+
+    /// Stream non-blank lines through `parse` and store the traces in
+    /// batches
+    async fn read_lines<R, F>(
+        &self,
+        reader: R,
+        options: &ImportOptions,
+        parse: F,
+    ) -> Result<ImportReport>
+    where
+        R: AsyncBufRead + Unpin,
+        F: Fn(&str) -> std::result::Result<Vec<Trace>, String>,
+    {
+        let mut report = ImportReport::default();
+        let mut batch: Vec<(usize, Trace)> = Vec::new();
+        let mut lines = reader.lines();
+        let mut line_number = 0;
+
+        while let Some(line) = lines.next_line().await? {
+            line_number += 1;
             if line.trim().is_empty() {
                 continue;
             }
 
-            let trace: Trace = serde_json::from_str(line)?;
-            self.log_trace(&trace).await?;
-            count += 1;
+            report.lines += 1;
+            match parse(&line) {
+                Ok(traces) => batch.extend(
+                    traces.into_iter().map(|trace| (line_number, trace)),
+                ),
+                Err(message) => report
+                    .errors
+                    .push(ImportError { line: line_number, message }),
+            }
+
+            if batch.len() >= options.batch_size {
+                self.import_batch(&mut batch, options, &mut report).await?;
+            }
         }
+        self.import_batch(&mut batch, options, &mut report).await?;
 
-        Ok(count)
+        // Parse errors were collected ahead of their batch
+        report.errors.sort_by_key(|error| error.line);
+        Ok(report)
     }
 
-    /// Convert a SQLx row to a Trace
-    fn row_to_trace(&self, row: sqlx::sqlite::SqliteRow) -> Result<Trace> {
-        let metadata_str: String = row.try_get("metadata")?;
-        let metadata: HashMap<String, Value> =
-            serde_json::from_str(&metadata_str)?;
-
-        let created_at_str: String = row.try_get("created_at")?;
-        let created_at = chrono::DateTime::parse_from_rfc3339(&created_at_str)
-            .map_err(|e| Error::Other(format!("Invalid datetime: {}", e)))?
-            .with_timezone(&Utc);
-
-        Ok(Trace {
-            id: row.try_get("id")?,
-            session_id: row.try_get("session_id")?,
-            role: row.try_get("role")?,
-            content: row.try_get("content")?,
-            metadata,
-            created_at,
-            embedding: row.try_get("embedding")?,
-        })
+    /// Write a batch of parsed lines and count the outcome
+    async fn import_batch(
+        &self,
+        batch: &mut Vec<(usize, Trace)>,
+        options: &ImportOptions,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let (line_numbers, traces): (Vec<usize>, Vec<Trace>) =
+            std::mem::take(batch).into_iter().unzip();
+        let usage: Vec<(i64, i64)> = traces
+            .iter()
+            .map(|trace| self.usage(std::slice::from_ref(trace)))
+            .collect();
+        let statuses = self
+            .store
+            .import_traces(&traces, &usage, options.duplicates)
+            .await?;
+
+        for (i, status) in statuses.into_iter().enumerate() {
+            if status == ImportStatus::Duplicate {
+                return Err(Error::Other(format!(
+                    "Line {}: duplicate trace id {}",
+                    line_numbers[i], traces[i].id
+                )));
+            }
+            report.record(status);
+        }
+
+        Ok(())
+    }
+
+    /// Export traces to a JSONL file, one [`Trace`] per line
+    ///
+    /// Traces are written oldest first and read back unchanged by
+    /// [`AgentHistory::import_jsonl`]. An existing file is overwritten.
+    ///
+    /// # Arguments
+    /// * `path` - File to write
+    /// * `filter` - Sessions and time range to export
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, ExportFilter};
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let history = AgentHistory::new("agent.db", Some("session-1")).await?;
+    ///
+    /// let count = history
+    ///     .export_jsonl("session-1.jsonl", &ExportFilter::all().current_session())
+    ///     .await?;
+    /// println!("Exported {} traces", count);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn export_jsonl(
+        &self,
+        path: &str,
+        filter: &ExportFilter,
+    ) -> Result<usize> {
+        let file = tokio::fs::File::create(path).await?;
+        self.write_jsonl(&mut BufWriter::new(file), filter).await
+    }
+
+    /// Write traces as JSONL to `writer`, one page at a time
+    ///
+    /// Like [`AgentHistory::export_jsonl`], but streams to any async writer
+    /// (a socket, a compressor, stdout) without loading the whole selection
+    /// into memory. The writer is flushed before returning.
+    pub async fn write_jsonl<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+        filter: &ExportFilter,
+    ) -> Result<usize> {
+        let filter = if filter.scope == SessionScope::Current {
+            filter
+                .clone()
+                .scope(SessionScope::Only(vec![self.session_id.clone()]))
+        } else {
+            filter.clone()
+        };
+
+        let mut count = 0;
+        let mut cursor: Option<Trace> = None;
+        loop {
+            let page = self
+                .store
+                .export_traces(&filter, cursor.as_ref(), EXPORT_PAGE_SIZE)
+                .await?;
+
+            for trace in &page {
+                let mut line = serde_json::to_vec(trace)?;
+                line.push(b'\n');
+                writer.write_all(&line).await?;
+            }
+            count += page.len();
+
+            if page.len() < EXPORT_PAGE_SIZE {
+                break;
+            }
+            cursor = page.into_iter().next_back();
+        }
+
+        writer.flush().await?;
+        Ok(count)
     }
 }
 
 /// Convert a Trace to a Rig Message
 fn trace_to_message(trace: Trace) -> Message {
-    Message { role: trace.role, content: trace.content }
+    Message { role: trace.role.to_string(), content: trace.content }
 }
@@ -0,0 +1,459 @@
+//! Options, reports and format conversions for importing traces
+//!
+//! Besides agentsmith's own JSONL, two foreign formats are understood:
+//! ChatGPT `conversations.json` exports and OpenAI chat-completions request
+//! logs. Both are mapped onto [`Trace`]s here and stored through the same
+//! batched path as JSONL imports.
+
+use crate::{Result, Trace, embedding::fnv1a};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::de::{Deserializer, SeqAccess, Visitor};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fmt;
+use std::io::BufReader;
+use tokio::sync::mpsc::Sender;
+
+/// What to do with an imported trace whose ID is already stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateStrategy {
+    /// Keep the stored trace and count the line as skipped
+    #[default]
+    Skip,
+
+    /// Stop the import with an error naming the line
+    Fail,
+
+    /// Replace the stored trace with the imported one
+    Upsert,
+}
+
+/// Outcome of storing one imported trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportStatus {
+    /// The trace was new and has been inserted
+    Inserted,
+
+    /// An existing trace was replaced ([`DuplicateStrategy::Upsert`])
+    Updated,
+
+    /// An existing trace was kept ([`DuplicateStrategy::Skip`])
+    Skipped,
+
+    /// The trace already exists and nothing after it was stored
+    /// ([`DuplicateStrategy::Fail`])
+    Duplicate,
+}
+
+/// Settings for [`AgentHistory::import_jsonl_with`](crate::AgentHistory::import_jsonl_with)
+///
+/// # Example
+/// ```rust
+/// use agentsmith::{DuplicateStrategy, ImportOptions};
+///
+/// let options = ImportOptions::default()
+///     .with_batch_size(1000)
+///     .with_duplicates(DuplicateStrategy::Upsert);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Traces written per transaction (default: 500)
+    pub batch_size: usize,
+
+    /// How traces with an existing ID are handled (default: skip)
+    pub duplicates: DuplicateStrategy,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self { batch_size: 500, duplicates: DuplicateStrategy::Skip }
+    }
+}
+
+impl ImportOptions {
+    /// Set how many traces are written per transaction
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Set how traces with an existing ID are handled
+    pub fn with_duplicates(mut self, duplicates: DuplicateStrategy) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
+}
+
+/// A line that could not be imported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportError {
+    /// 1-based line number in the input (for ChatGPT exports, the
+    /// position of the conversation)
+    pub line: usize,
+
+    /// Why the line was rejected
+    pub message: String,
+}
+
+/// Summary of an import
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Non-blank lines (or ChatGPT conversations) read
+    pub lines: usize,
+
+    /// Traces inserted
+    pub inserted: usize,
+
+    /// Existing traces replaced
+    pub updated: usize,
+
+    /// Duplicate traces left as they were
+    pub skipped: usize,
+
+    /// Lines that could not be parsed, in input order
+    pub errors: Vec<ImportError>,
+}
+
+impl ImportReport {
+    /// Traces inserted or replaced
+    pub fn imported(&self) -> usize {
+        self.inserted + self.updated
+    }
+
+    /// Count the outcome of one stored trace
+    pub(crate) fn record(&mut self, status: ImportStatus) {
+        match status {
+            ImportStatus::Inserted => self.inserted += 1,
+            ImportStatus::Updated => self.updated += 1,
+            ImportStatus::Skipped => self.skipped += 1,
+            ImportStatus::Duplicate => {}
+        }
+    }
+}
+
+/// Parse a ChatGPT `conversations.json` export, sending each conversation
+/// as soon as it is read
+///
+/// Blocks, so run it on a blocking thread. Stops early once the receiver
+/// is dropped.
+pub(crate) fn read_chatgpt_export(
+    file: std::fs::File,
+    sender: Sender<Value>,
+) -> Result<()> {
+    let mut deserializer =
+        serde_json::Deserializer::from_reader(BufReader::new(file));
+    deserializer.deserialize_seq(Conversations(sender))?;
+    deserializer.end()?;
+    Ok(())
+}
+
+/// Visits the top-level array of a ChatGPT export without collecting it
+struct Conversations(Sender<Value>);
+
+impl<'de> Visitor<'de> for Conversations {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an array of conversations")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> std::result::Result<(), A::Error> {
+        while let Some(conversation) = seq.next_element::<Value>()? {
+            // The import gave up; nobody is left to store the rest
+            if self.0.blocking_send(conversation).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Convert one conversation of a ChatGPT `conversations.json` export
+///
+/// ChatGPT stores each conversation as a tree of messages, branching
+/// wherever a reply was edited or regenerated. Only the branch that ends at
+/// `current_node` (what the user last saw) is imported, root first. Empty
+/// and content-less nodes are dropped. Message IDs are kept, so importing
+/// the same export twice finds the traces already stored.
+pub(crate) fn chatgpt_traces(
+    conversation: &Value,
+) -> std::result::Result<Vec<Trace>, String> {
+    let session_id = conversation["conversation_id"]
+        .as_str()
+        .or_else(|| conversation["id"].as_str())
+        .ok_or("conversation has no id")?;
+    let mapping = conversation["mapping"]
+        .as_object()
+        .ok_or("conversation has no message mapping")?;
+    let title = conversation["title"].as_str();
+    let started_at = timestamp(&conversation["create_time"]);
+
+    // Walk up from the visible leaf; `seen` guards against broken parents
+    let mut node_id = conversation["current_node"]
+        .as_str()
+        .map(str::to_string)
+        .or_else(|| latest_leaf(mapping));
+    let mut branch = Vec::new();
+    let mut seen = HashSet::new();
+    while let Some(id) = node_id {
+        let Some(node) = mapping.get(&id) else { break };
+        if !seen.insert(id) {
+            break;
+        }
+        branch.push(node);
+        node_id = node["parent"].as_str().map(str::to_string);
+    }
+    branch.reverse();
+
+    let mut traces = Vec::new();
+    for node in branch {
+        let message = &node["message"];
+        let Some(content) = chatgpt_content(&message["content"]) else {
+            continue;
+        };
+        if content.trim().is_empty() {
+            continue;
+        }
+
+        let role = message["author"]["role"].as_str().unwrap_or("unknown");
+        let mut trace = Trace::new(session_id.to_string(), role, content);
+        if let Some(id) =
+            message["id"].as_str().or_else(|| node["id"].as_str())
+        {
+            trace.id = id.to_string();
+        }
+        if let Some(created_at) =
+            timestamp(&message["create_time"]).or(started_at)
+        {
+            trace.created_at = created_at;
+        }
+
+        let metadata = &mut trace.metadata;
+        metadata.insert("source".to_string(), "chatgpt".into());
+        if let Some(title) = title {
+            metadata.insert("conversation_title".to_string(), title.into());
+        }
+        if let Some(model) = message["metadata"]["model_slug"].as_str() {
+            metadata.insert("model".to_string(), model.into());
+        }
+        if let Some(name) = message["author"]["name"].as_str() {
+            metadata.insert("author_name".to_string(), name.into());
+        }
+        // Assistant messages addressed to a tool are tool calls
+        if let Some(recipient) = message["recipient"].as_str()
+            && recipient != "all"
+        {
+            metadata.insert("recipient".to_string(), recipient.into());
+        }
+        if let Some(kind) = message["content"]["content_type"].as_str()
+            && kind != "text"
+        {
+            metadata.insert("content_type".to_string(), kind.into());
+        }
+
+        traces.push(trace);
+    }
+
+    Ok(traces)
+}
+
+/// Convert one line of an OpenAI chat-completions request log
+///
+/// A line is a request body (`{"model", "messages", ...}`), or an object
+/// with `request` and `response` fields holding the request body and the
+/// `chat.completion` reply. Every message of the request becomes a trace,
+/// followed by the first choice of the reply. Tool calls are kept in the
+/// `tool_calls` metadata and, when the message has no text, rendered as
+/// `name(arguments)` lines.
+///
+/// The session is the line's `session_id` or `conversation_id` field,
+/// falling back to the completion ID. Trace IDs are derived from the
+/// session, position and content: logs repeat the whole conversation on
+/// every call, and the repeated messages then resolve to traces already
+/// imported from earlier lines.
+pub(crate) fn openai_log_traces(
+    record: &Value,
+) -> std::result::Result<Vec<Trace>, String> {
+    let request = if record["request"].is_object() {
+        &record["request"]
+    } else {
+        record
+    };
+    let response = if record["response"].is_object() {
+        &record["response"]
+    } else {
+        record
+    };
+    let messages =
+        request["messages"].as_array().ok_or("request has no messages")?;
+
+    let session_id = record["session_id"]
+        .as_str()
+        .or_else(|| record["conversation_id"].as_str())
+        .or_else(|| response["id"].as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| stable_id("openai", &[&request.to_string()]));
+    let model =
+        response["model"].as_str().or_else(|| request["model"].as_str());
+    let base = timestamp(&response["created"])
+        .or_else(|| timestamp(&record["created"]))
+        .or_else(|| timestamp(&record["timestamp"]))
+        .unwrap_or_else(Utc::now);
+
+    let reply = response["choices"][0]["message"]
+        .is_object()
+        .then(|| &response["choices"][0]["message"]);
+
+    let mut traces = Vec::new();
+    for (position, message) in messages.iter().chain(reply).enumerate() {
+        let Some(mut trace) = openai_trace(&session_id, position, message)?
+        else {
+            continue;
+        };
+        // Logs only time the call; keep the messages in request order
+        trace.created_at = base + TimeDelta::microseconds(position as i64);
+
+        let metadata = &mut trace.metadata;
+        metadata.insert("source".to_string(), "openai".into());
+        if position == messages.len() {
+            if let Some(model) = model {
+                metadata.insert("model".to_string(), model.into());
+            }
+            if let Some(tokens) = response["usage"]["total_tokens"].as_u64() {
+                metadata.insert("tokens_used".to_string(), tokens.into());
+            }
+            if let Some(reason) =
+                response["choices"][0]["finish_reason"].as_str()
+            {
+                metadata.insert("finish_reason".to_string(), reason.into());
+            }
+        }
+
+        traces.push(trace);
+    }
+
+    Ok(traces)
+}
+
+/// Map one chat-completions message, or `None` if it carries nothing
+fn openai_trace(
+    session_id: &str,
+    position: usize,
+    message: &Value,
+) -> std::result::Result<Option<Trace>, String> {
+    let role = message["role"]
+        .as_str()
+        .ok_or_else(|| format!("message {} has no role", position + 1))?;
+    let tool_calls =
+        message["tool_calls"].as_array().filter(|c| !c.is_empty());
+
+    let mut content = openai_content(&message["content"]);
+    if content.trim().is_empty()
+        && let Some(calls) = tool_calls
+    {
+        let lines: Vec<String> = calls
+            .iter()
+            .map(|call| {
+                format!(
+                    "{}({})",
+                    call["function"]["name"].as_str().unwrap_or("tool"),
+                    call["function"]["arguments"].as_str().unwrap_or("")
+                )
+            })
+            .collect();
+        content = lines.join("\n");
+    }
+    if content.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let id = stable_id(
+        "openai",
+        &[session_id, &position.to_string(), role, &content],
+    );
+    let mut trace = Trace::new(session_id.to_string(), role, content);
+    trace.id = id;
+
+    if let Some(calls) = tool_calls {
+        trace
+            .metadata
+            .insert("tool_calls".to_string(), Value::Array(calls.clone()));
+    }
+    if let Some(call_id) = message["tool_call_id"].as_str() {
+        trace.metadata.insert("tool_call_id".to_string(), call_id.into());
+    }
+    if let Some(name) = message["name"].as_str() {
+        trace.metadata.insert("author_name".to_string(), name.into());
+    }
+
+    Ok(Some(trace))
+}
+
+/// Text of a ChatGPT message: its string `parts`, or its `text` for code
+/// and tool output. Attachments and other non-text parts are left out.
+fn chatgpt_content(content: &Value) -> Option<String> {
+    if let Some(parts) = content["parts"].as_array() {
+        let texts: Vec<&str> =
+            parts.iter().filter_map(Value::as_str).collect();
+        return Some(texts.join("\n"));
+    }
+    content["text"].as_str().map(str::to_string)
+}
+
+/// Text of a chat-completions message: a string, or the `text` of each
+/// content part
+fn openai_content(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => {
+            let texts: Vec<&str> = parts
+                .iter()
+                .filter_map(|part| part["text"].as_str())
+                .collect();
+            texts.join("\n")
+        }
+        _ => String::new(),
+    }
+}
+
+/// The most recent leaf of a message tree, for exports without
+/// `current_node`
+fn latest_leaf(mapping: &Map<String, Value>) -> Option<String> {
+    mapping
+        .iter()
+        .filter(|(_, node)| {
+            node["children"].as_array().is_none_or(|c| c.is_empty())
+        })
+        .max_by(|(_, a), (_, b)| {
+            let a = a["message"]["create_time"].as_f64().unwrap_or(0.0);
+            let b = b["message"]["create_time"].as_f64().unwrap_or(0.0);
+            a.total_cmp(&b)
+        })
+        .map(|(id, _)| id.clone())
+}
+
+/// Parse Unix seconds (possibly fractional) or an RFC 3339 string
+fn timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::Number(n) => {
+            let seconds = n.as_f64()?;
+            DateTime::from_timestamp_micros((seconds * 1e6).round() as i64)
+        }
+        Value::String(s) => {
+            DateTime::parse_from_rfc3339(s).ok().map(|t| t.with_timezone(&Utc))
+        }
+        _ => None,
+    }
+}
+
+/// A deterministic ID from the given parts (64-bit FNV-1a)
+///
+/// `DefaultHasher` may change between Rust releases, which would break
+/// re-imports of the same log.
+fn stable_id(prefix: &str, parts: &[&str]) -> String {
+    let hash = fnv1a(parts.iter().flat_map(|part| part.bytes().chain([0])));
+    format!("{}-{:016x}", prefix, hash)
+}
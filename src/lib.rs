@@ -23,12 +23,47 @@
 //! # }
 //! ```
 
+mod context;
+mod embedding;
 mod error;
 mod history;
+mod import;
+mod query;
+mod retriever;
+mod retry;
+mod role;
+mod session;
 mod smart_agent;
+mod store;
+mod summary;
 mod trace;
+mod transcript;
 
+pub use context::{
+    CharHeuristic, ContextBuilder, ContextWindow, TokenCounter,
+};
+pub use embedding::{
+    EmbedFuture, Embedder, HashEmbedder, RigEmbedder, cosine_similarity,
+};
 pub use error::{Error, Result};
 pub use history::AgentHistory;
-pub use smart_agent::SmartAgent;
+pub use import::{
+    DuplicateStrategy, ImportError, ImportOptions, ImportReport, ImportStatus,
+};
+pub use query::{
+    CompareOp, ExportFilter, MetadataFilter, SearchQuery, SessionScope,
+    build_fts_query,
+};
+pub use retriever::{Dedupe, HybridConfig, RecallOptions, Retriever};
+pub use retry::RetryPolicy;
+pub use role::Role;
+pub use session::{Session, SessionSummary};
+pub use smart_agent::{
+    ChatStream, ChunkStream, SmartAgent, SummaryStatus, SummaryTrigger,
+};
+#[cfg(feature = "postgres")]
+pub use store::PostgresStore;
+pub use store::{HistoryStore, MemoryStore, SqliteStore, StoreFuture};
+pub use summary::{SummaryConfig, SummaryFormat};
 pub use trace::Trace;
+pub use transcript::{Badges, TranscriptFormat, TranscriptOptions};
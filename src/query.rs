@@ -0,0 +1,327 @@
+//! Query building for full-text search over traces
+
+use crate::Role;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+/// Minimum term length that gets prefix matching (shorter terms match exactly)
+const MIN_PREFIX_LEN: usize = 3;
+
+/// Turn raw natural-language text into a valid FTS5 MATCH expression
+///
+/// The input is split into terms on anything that isn't alphanumeric or `_`,
+/// each term is quoted (so FTS5 operators and punctuation are taken
+/// literally) and the terms are OR-ed together. Terms of three or more
+/// characters use prefix matching, so `pars` finds `parsing`.
+///
+/// Returns `None` if the input contains no searchable terms.
+///
+/// # Example
+/// ```rust
+/// use agentsmith::build_fts_query;
+///
+/// assert_eq!(
+///     build_fts_query(r#"what's "json"?"#).as_deref(),
+///     Some(r#""what"* OR "s" OR "json"*"#)
+/// );
+/// assert_eq!(build_fts_query("C++").as_deref(), Some(r#""C""#));
+/// assert_eq!(build_fts_query("?!"), None);
+/// ```
+pub fn build_fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = query_terms(input)
+        .map(|t| {
+            if is_prefix_term(t) {
+                format!("\"{}\"*", t)
+            } else {
+                format!("\"{}\"", t)
+            }
+        })
+        .collect();
+
+    if terms.is_empty() { None } else { Some(terms.join(" OR ")) }
+}
+
+/// Split search input into terms on anything that isn't alphanumeric or `_`
+pub(crate) fn query_terms(input: &str) -> impl Iterator<Item = &str> {
+    input
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|t| !t.is_empty())
+}
+
+/// Whether a term is long enough to use prefix matching
+pub(crate) fn is_prefix_term(term: &str) -> bool {
+    term.chars().count() >= MIN_PREFIX_LEN
+}
+
+/// Which sessions a [`SearchQuery`] covers
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SessionScope {
+    /// Only the history's current session
+    Current,
+
+    /// Every session in the database
+    #[default]
+    All,
+
+    /// Only the listed sessions
+    Only(Vec<String>),
+}
+
+/// Comparison operator for a [`MetadataFilter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    /// Equal to
+    Eq,
+    /// Not equal to
+    Ne,
+    /// Greater than
+    Gt,
+    /// Greater than or equal to
+    Gte,
+    /// Less than
+    Lt,
+    /// Less than or equal to
+    Lte,
+}
+
+impl CompareOp {
+    /// SQL operator for this comparison
+    pub(crate) fn as_sql(&self) -> &'static str {
+        match self {
+            CompareOp::Eq => "=",
+            CompareOp::Ne => "!=",
+            CompareOp::Gt => ">",
+            CompareOp::Gte => ">=",
+            CompareOp::Lt => "<",
+            CompareOp::Lte => "<=",
+        }
+    }
+}
+
+/// A predicate on a top-level metadata field, evaluated with `json_extract`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetadataFilter {
+    /// Metadata key (e.g. `duration_ms`)
+    pub key: String,
+
+    /// Comparison operator
+    pub op: CompareOp,
+
+    /// Value to compare against; `null` matches missing or null fields
+    pub value: Value,
+}
+
+/// Structured trace search, pushed down into SQL
+///
+/// All filters are applied by the database before `LIMIT`, so a query
+/// returns up to `limit` traces that match every filter. With text, results
+/// are ordered by relevance; without, newest first.
+///
+/// # Example
+/// ```rust,no_run
+/// # use agentsmith::{AgentHistory, CompareOp, SearchQuery};
+/// # use serde_json::json;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// # let history = AgentHistory::new("agent.db", None).await?;
+/// let query = SearchQuery::new("deploy")
+///     .role("assistant")
+///     .current_session()
+///     .metadata("duration_ms", CompareOp::Gt, json!(500))
+///     .success_only(true)
+///     .limit(10);
+///
+/// let slow_answers = history.query(&query).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchQuery {
+    /// Text to search for; `None` matches every trace
+    pub text: Option<String>,
+
+    /// Whether `text` is a raw FTS5 expression rather than plain text
+    pub raw: bool,
+
+    /// Allowed roles; empty allows all
+    pub roles: Vec<Role>,
+
+    /// Sessions to search
+    pub scope: SessionScope,
+
+    /// Earliest `created_at` to include
+    pub since: Option<DateTime<Utc>>,
+
+    /// Latest `created_at` to include
+    pub until: Option<DateTime<Utc>>,
+
+    /// Metadata predicates, all of which must hold
+    pub metadata: Vec<MetadataFilter>,
+
+    /// Exclude traces marked `success: false`
+    pub success_only: bool,
+
+    /// Maximum number of results
+    pub limit: usize,
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        Self {
+            text: None,
+            raw: false,
+            roles: Vec::new(),
+            scope: SessionScope::default(),
+            since: None,
+            until: None,
+            metadata: Vec::new(),
+            success_only: false,
+            limit: 10,
+        }
+    }
+}
+
+impl SearchQuery {
+    /// Search for natural-language text (escaped with [`build_fts_query`])
+    pub fn new(text: &str) -> Self {
+        Self { text: Some(text.to_string()), ..Self::default() }
+    }
+
+    /// Search with a raw FTS5 MATCH expression
+    pub fn raw(fts_query: &str) -> Self {
+        Self {
+            text: Some(fts_query.to_string()),
+            raw: true,
+            ..Self::default()
+        }
+    }
+
+    /// Match every trace, subject to the other filters (newest first)
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Only include traces with this role (may be called repeatedly)
+    ///
+    /// Roles match regardless of case, so rows stored as `"Assistant"`
+    /// before roles were normalized are still found.
+    pub fn role(mut self, role: impl Into<Role>) -> Self {
+        self.roles.push(role.into());
+        self
+    }
+
+    /// Set which sessions to search (default: all)
+    pub fn scope(mut self, scope: SessionScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Only search the history's current session
+    pub fn current_session(self) -> Self {
+        self.scope(SessionScope::Current)
+    }
+
+    /// Only search the given sessions
+    pub fn sessions<I, S>(self, ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.scope(SessionScope::Only(
+            ids.into_iter().map(Into::into).collect(),
+        ))
+    }
+
+    /// Only include traces created at or after `since`
+    pub fn since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Only include traces created at or before `until`
+    pub fn until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Add a metadata predicate (e.g. `duration_ms > 500`)
+    pub fn metadata(mut self, key: &str, op: CompareOp, value: Value) -> Self {
+        self.metadata.push(MetadataFilter { key: key.to_string(), op, value });
+        self
+    }
+
+    /// Only include traces not marked `success: false`
+    pub fn success_only(mut self, success_only: bool) -> Self {
+        self.success_only = success_only;
+        self
+    }
+
+    /// Set the maximum number of results (default: 10)
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+/// Traces selected by [`AgentHistory::export_jsonl`](crate::AgentHistory::export_jsonl)
+///
+/// # Example
+/// ```rust
+/// use agentsmith::ExportFilter;
+/// use chrono::{Duration, Utc};
+///
+/// let last_week = ExportFilter::all()
+///     .sessions(["support-1", "support-2"])
+///     .since(Utc::now() - Duration::days(7));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportFilter {
+    /// Sessions to export
+    pub scope: SessionScope,
+
+    /// Earliest `created_at` to include
+    pub since: Option<DateTime<Utc>>,
+
+    /// Latest `created_at` to include
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl ExportFilter {
+    /// Select every trace in the database
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Set which sessions to export (default: all)
+    pub fn scope(mut self, scope: SessionScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Only export the history's current session
+    pub fn current_session(self) -> Self {
+        self.scope(SessionScope::Current)
+    }
+
+    /// Only export the given sessions
+    pub fn sessions<I, S>(self, ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.scope(SessionScope::Only(
+            ids.into_iter().map(Into::into).collect(),
+        ))
+    }
+
+    /// Only include traces created at or after `since`
+    pub fn since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Only include traces created at or before `until`
+    pub fn until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+}
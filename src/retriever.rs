@@ -0,0 +1,366 @@
+//! Recall strategies used by SmartAgent to find relevant past traces
+
+use crate::{AgentHistory, Result, Role, Trace};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet, hash_map::DefaultHasher},
+    hash::{Hash, Hasher},
+};
+
+/// Strategy for recalling relevant traces from history
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Retriever {
+    /// FTS5 keyword search only (BM25 ranking)
+    #[default]
+    FullText,
+
+    /// Embedding similarity only (requires an embedder on the history)
+    Semantic,
+
+    /// Keyword and embedding results fused with reciprocal rank fusion
+    Hybrid(HybridConfig),
+}
+
+/// Weights and tuning for [`Retriever::Hybrid`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HybridConfig {
+    /// Weight applied to the full-text ranking (default: 1.0)
+    pub fts_weight: f64,
+
+    /// Weight applied to the embedding ranking (default: 1.0)
+    pub vector_weight: f64,
+
+    /// RRF smoothing constant; larger values flatten rank differences (default: 60)
+    pub rrf_k: f64,
+
+    /// How many candidates to fetch from each source per requested result (default: 4)
+    pub candidate_multiplier: usize,
+}
+
+impl Default for HybridConfig {
+    fn default() -> Self {
+        Self {
+            fts_weight: 1.0,
+            vector_weight: 1.0,
+            rrf_k: 60.0,
+            candidate_multiplier: 4,
+        }
+    }
+}
+
+impl HybridConfig {
+    /// Set the weight of the full-text ranking
+    pub fn with_fts_weight(mut self, weight: f64) -> Self {
+        self.fts_weight = weight;
+        self
+    }
+
+    /// Set the weight of the embedding ranking
+    pub fn with_vector_weight(mut self, weight: f64) -> Self {
+        self.vector_weight = weight;
+        self
+    }
+
+    /// Set the RRF smoothing constant
+    pub fn with_rrf_k(mut self, k: f64) -> Self {
+        self.rrf_k = k;
+        self
+    }
+
+    /// Set how many candidates to fetch per requested result
+    pub fn with_candidate_multiplier(mut self, multiplier: usize) -> Self {
+        self.candidate_multiplier = multiplier.max(1);
+        self
+    }
+}
+
+/// How recalled traces with the same content are collapsed
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Dedupe {
+    /// Keep every trace
+    #[default]
+    Off,
+
+    /// Collapse traces with identical role and content
+    Exact,
+
+    /// Collapse traces whose content matches ignoring case, punctuation and
+    /// whitespace
+    Normalized,
+}
+
+/// Post-processing applied to recalled traces
+///
+/// # Example
+/// ```rust
+/// use agentsmith::{Dedupe, RecallOptions};
+///
+/// let options = RecallOptions::default()
+///     .with_exclude_recent(6)
+///     .with_dedupe(Dedupe::Normalized)
+///     .with_pair_answers(true);
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecallOptions {
+    /// Skip the last N traces of the current session (default: 0)
+    pub exclude_recent: usize,
+
+    /// How duplicate content is collapsed (default: off)
+    pub dedupe: Dedupe,
+
+    /// Recall a user question together with the assistant answer that
+    /// followed it, as one result (default: false)
+    pub pair_answers: bool,
+
+    /// How many candidates to fetch per requested result when filtering
+    /// (default: 3)
+    pub candidate_multiplier: usize,
+}
+
+impl Default for RecallOptions {
+    fn default() -> Self {
+        Self {
+            exclude_recent: 0,
+            dedupe: Dedupe::Off,
+            pair_answers: false,
+            candidate_multiplier: 3,
+        }
+    }
+}
+
+impl RecallOptions {
+    /// Skip the last `n` traces of the current session
+    pub fn with_exclude_recent(mut self, n: usize) -> Self {
+        self.exclude_recent = n;
+        self
+    }
+
+    /// Set how duplicate content is collapsed
+    pub fn with_dedupe(mut self, dedupe: Dedupe) -> Self {
+        self.dedupe = dedupe;
+        self
+    }
+
+    /// Recall question and answer pairs instead of single traces
+    pub fn with_pair_answers(mut self, pair: bool) -> Self {
+        self.pair_answers = pair;
+        self
+    }
+
+    /// Set how many candidates to fetch per requested result
+    pub fn with_candidate_multiplier(mut self, multiplier: usize) -> Self {
+        self.candidate_multiplier = multiplier.max(1);
+        self
+    }
+
+    /// Whether any post-processing is enabled
+    fn is_noop(&self) -> bool {
+        self.exclude_recent == 0
+            && self.dedupe == Dedupe::Off
+            && !self.pair_answers
+    }
+}
+
+impl Retriever {
+    /// Hybrid retrieval with default weights
+    pub fn hybrid() -> Self {
+        Retriever::Hybrid(HybridConfig::default())
+    }
+
+    /// Recall up to `limit` traces relevant to `query`
+    pub async fn retrieve(
+        &self,
+        history: &AgentHistory,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Trace>> {
+        match self {
+            Retriever::FullText => history.search(query, limit, false).await,
+            Retriever::Semantic => history.semantic_search(query, limit).await,
+            Retriever::Hybrid(config) => {
+                hybrid_search(history, query, limit, config).await
+            }
+        }
+    }
+
+    /// Recall up to `limit` results, filtered according to `options`
+    ///
+    /// With [`RecallOptions::pair_answers`] a result may be a question
+    /// followed by its answer, so up to `2 * limit` traces can be returned.
+    pub async fn recall(
+        &self,
+        history: &AgentHistory,
+        query: &str,
+        limit: usize,
+        options: &RecallOptions,
+    ) -> Result<Vec<Trace>> {
+        if options.is_noop() {
+            return self.retrieve(history, query, limit).await;
+        }
+
+        let candidates = self
+            .retrieve(
+                history,
+                query,
+                limit.saturating_mul(options.candidate_multiplier),
+            )
+            .await?;
+
+        let excluded: HashSet<String> = if options.exclude_recent > 0 {
+            history
+                .recent(options.exclude_recent)
+                .await?
+                .into_iter()
+                .map(|trace| trace.id)
+                .collect()
+        } else {
+            HashSet::new()
+        };
+
+        let mut sessions: HashMap<String, Vec<Trace>> = HashMap::new();
+        let mut seen_ids = HashSet::new();
+        let mut seen_content = HashSet::new();
+        let mut results = Vec::new();
+        let mut groups = 0;
+
+        for trace in candidates {
+            if groups >= limit {
+                break;
+            }
+
+            let group = if options.pair_answers {
+                pair_turn(history, &mut sessions, trace).await?
+            } else {
+                vec![trace]
+            };
+
+            if group
+                .iter()
+                .any(|t| excluded.contains(&t.id) || seen_ids.contains(&t.id))
+            {
+                continue;
+            }
+
+            let keys: Vec<u64> = match options.dedupe {
+                Dedupe::Off => Vec::new(),
+                Dedupe::Exact => group
+                    .iter()
+                    .map(|t| content_hash(t.role.as_str(), &t.content))
+                    .collect(),
+                Dedupe::Normalized => group
+                    .iter()
+                    .map(|t| {
+                        content_hash(t.role.as_str(), &normalize(&t.content))
+                    })
+                    .collect(),
+            };
+            if keys.iter().any(|key| seen_content.contains(key)) {
+                continue;
+            }
+
+            seen_content.extend(keys);
+            seen_ids.extend(group.iter().map(|t| t.id.clone()));
+            results.extend(group);
+            groups += 1;
+        }
+
+        Ok(results)
+    }
+}
+
+/// Expand a trace into its question and answer pair, if it has one
+///
+/// Session traces are loaded once per call and cached in `sessions`.
+async fn pair_turn(
+    history: &AgentHistory,
+    sessions: &mut HashMap<String, Vec<Trace>>,
+    trace: Trace,
+) -> Result<Vec<Trace>> {
+    if trace.role != Role::User && trace.role != Role::Assistant {
+        return Ok(vec![trace]);
+    }
+
+    if !sessions.contains_key(&trace.session_id) {
+        let traces = history.store().session_traces(&trace.session_id).await?;
+        sessions.insert(trace.session_id.clone(), traces);
+    }
+    let traces = &sessions[&trace.session_id];
+    let Some(pos) = traces.iter().position(|t| t.id == trace.id) else {
+        return Ok(vec![trace]);
+    };
+
+    let partner = if trace.role == Role::User {
+        traces.get(pos + 1).filter(|t| t.role == Role::Assistant)
+    } else {
+        pos.checked_sub(1)
+            .and_then(|i| traces.get(i))
+            .filter(|t| t.role == Role::User)
+    };
+
+    Ok(match partner.cloned() {
+        Some(answer) if trace.role == Role::User => vec![trace, answer],
+        Some(question) => vec![question, trace],
+        None => vec![trace],
+    })
+}
+
+/// Hash of a trace's role and (possibly normalized) content
+fn content_hash(role: &str, content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    role.hash(&mut hasher);
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Lowercase `text` and keep only its alphanumeric words
+fn normalize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Fuse FTS and embedding rankings with weighted reciprocal rank fusion
+///
+/// Falls back to full-text results alone when the history has no embedder.
+async fn hybrid_search(
+    history: &AgentHistory,
+    query: &str,
+    limit: usize,
+    config: &HybridConfig,
+) -> Result<Vec<Trace>> {
+    let candidates = limit.saturating_mul(config.candidate_multiplier);
+
+    let fts = history.search(query, candidates, false).await?;
+    let semantic = if history.embedder().is_some() {
+        history.semantic_search(query, candidates).await?
+    } else {
+        Vec::new()
+    };
+
+    let mut fused: HashMap<String, (Trace, f64)> = HashMap::new();
+    for (ranking, weight) in
+        [(fts, config.fts_weight), (semantic, config.vector_weight)]
+    {
+        for (rank, trace) in ranking.into_iter().enumerate() {
+            let score = weight / (config.rrf_k + rank as f64 + 1.0);
+            fused
+                .entry(trace.id.clone())
+                .and_modify(|(_, s)| *s += score)
+                .or_insert((trace, score));
+        }
+    }
+
+    let mut results: Vec<(Trace, f64)> = fused.into_values().collect();
+    results.sort_by(|a, b| {
+        b.1.total_cmp(&a.1).then(b.0.created_at.cmp(&a.0.created_at))
+    });
+    results.truncate(limit);
+
+    Ok(results.into_iter().map(|(trace, _)| trace).collect())
+}
@@ -0,0 +1,112 @@
+//! Retry policy for SmartAgent model calls
+
+use rig::completion::{CompletionError, PromptError};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Decides whether a failed call is worth retrying
+type Retryable = Arc<dyn Fn(&PromptError) -> bool + Send + Sync>;
+
+/// How failed agent calls are retried
+///
+/// The delay before retry `n` is `initial_backoff * multiplier^(n - 1)`,
+/// capped at `max_backoff`. By default only HTTP and provider errors are
+/// retried; malformed requests and tool failures would fail again.
+///
+/// # Example
+/// ```rust
+/// use agentsmith::RetryPolicy;
+/// use rig::completion::PromptError;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::default()
+///     .with_max_attempts(5)
+///     .with_backoff(Duration::from_millis(200), 3.0)
+///     .with_retryable(|e| !matches!(e, PromptError::ToolError(_)));
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    initial_backoff: Duration,
+    multiplier: f64,
+    max_backoff: Duration,
+    retryable: Retryable,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            multiplier: 2.0,
+            max_backoff: Duration::from_secs(10),
+            retryable: Arc::new(is_transient),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that makes a single attempt
+    pub fn none() -> Self {
+        Self::default().with_max_attempts(1)
+    }
+
+    /// Set the total number of attempts, including the first (default: 3)
+    pub fn with_max_attempts(mut self, attempts: usize) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Set the first delay and the factor it grows by on each retry
+    /// (default: 500ms, doubling)
+    pub fn with_backoff(mut self, initial: Duration, multiplier: f64) -> Self {
+        self.initial_backoff = initial;
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Set the longest delay between attempts (default: 10s)
+    pub fn with_max_backoff(mut self, max: Duration) -> Self {
+        self.max_backoff = max;
+        self
+    }
+
+    /// Set which errors are retried
+    pub fn with_retryable<F>(mut self, retryable: F) -> Self
+    where
+        F: Fn(&PromptError) -> bool + Send + Sync + 'static,
+    {
+        self.retryable = Arc::new(retryable);
+        self
+    }
+
+    /// Get the total number of attempts
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    /// Whether a call that failed with `error` should be retried
+    pub fn is_retryable(&self, error: &PromptError) -> bool {
+        (self.retryable)(error)
+    }
+
+    /// Delay before retry number `retry` (starting at 1)
+    pub fn backoff(&self, retry: usize) -> Duration {
+        let exponent = retry.saturating_sub(1).min(i32::MAX as usize) as i32;
+        let delay = self.initial_backoff.as_secs_f64()
+            * self.multiplier.powi(exponent);
+        Duration::try_from_secs_f64(delay)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// Default retry predicate: connection and provider-side errors
+fn is_transient(error: &PromptError) -> bool {
+    matches!(
+        error,
+        PromptError::CompletionError(
+            CompletionError::HttpError(_) | CompletionError::ProviderError(_)
+        )
+    )
+}
@@ -0,0 +1,158 @@
+//! Typed message roles
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::{
+    Database, Decode, Encode, Type, encode::IsNull, error::BoxDynError,
+};
+use std::fmt;
+
+/// Who a trace came from
+///
+/// Roles are stored as lowercase strings. Parsing ignores case and
+/// surrounding whitespace for the known roles, so rows written as
+/// `"Assistant"` read back as [`Role::Assistant`]; any other string is kept
+/// verbatim as [`Role::Other`].
+///
+/// # Example
+/// ```rust
+/// use agentsmith::Role;
+///
+/// assert_eq!(Role::from("Assistant"), Role::Assistant);
+/// assert_eq!(Role::from("critic"), Role::Other("critic".to_string()));
+/// assert_eq!(Role::User.as_str(), "user");
+/// assert!(Role::System == "system");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Role {
+    /// Instructions and injected context
+    System,
+
+    /// The person talking to the agent
+    User,
+
+    /// The model's replies
+    Assistant,
+
+    /// Tool or function output
+    Tool,
+
+    /// A failed model call logged by [`SmartAgent`](crate::SmartAgent)
+    Error,
+
+    /// Any other role, as written
+    Other(String),
+}
+
+impl Role {
+    /// The role as stored
+    pub fn as_str(&self) -> &str {
+        match self {
+            Role::System => "system",
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+            Role::Error => "error",
+            Role::Other(role) => role,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for Role {
+    fn from(role: &str) -> Self {
+        match role.trim().to_ascii_lowercase().as_str() {
+            "system" => Role::System,
+            "user" => Role::User,
+            "assistant" => Role::Assistant,
+            "tool" => Role::Tool,
+            "error" => Role::Error,
+            _ => Role::Other(role.to_string()),
+        }
+    }
+}
+
+impl From<String> for Role {
+    fn from(role: String) -> Self {
+        Role::from(role.as_str())
+    }
+}
+
+impl From<&String> for Role {
+    fn from(role: &String) -> Self {
+        Role::from(role.as_str())
+    }
+}
+
+/// Compares the way [`Role::from`] parses
+impl PartialEq<str> for Role {
+    fn eq(&self, other: &str) -> bool {
+        match self {
+            Role::Other(role) => role == other,
+            known => known.as_str().eq_ignore_ascii_case(other.trim()),
+        }
+    }
+}
+
+impl PartialEq<&str> for Role {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl Serialize for Role {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Role {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Role::from)
+    }
+}
+
+impl<DB: Database> Type<DB> for Role
+where
+    String: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <String as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <String as Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, DB: Database> Encode<'q, DB> for Role
+where
+    String: Encode<'q, DB>,
+{
+    fn encode_by_ref(
+        &self,
+        buf: &mut <DB as Database>::ArgumentBuffer<'q>,
+    ) -> Result<IsNull, BoxDynError> {
+        self.as_str().to_string().encode(buf)
+    }
+}
+
+impl<'r, DB: Database> Decode<'r, DB> for Role
+where
+    String: Decode<'r, DB>,
+{
+    fn decode(
+        value: <DB as Database>::ValueRef<'r>,
+    ) -> Result<Self, BoxDynError> {
+        String::decode(value).map(Role::from)
+    }
+}
@@ -0,0 +1,100 @@
+//! Session data structures for grouping agent interactions
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A conversation session grouping a sequence of traces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    /// Unique identifier for this session
+    pub id: String,
+
+    /// Latest summary of the session, if one has been generated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+
+    /// ID of the last trace covered by the summary
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary_checkpoint: Option<String>,
+
+    /// When this session was last written to
+    pub updated_at: DateTime<Utc>,
+
+    /// Number of traces stored for this session
+    #[serde(default)]
+    pub trace_count: usize,
+
+    /// Characters logged since the last summary
+    #[serde(default)]
+    pub unsummarized_chars: usize,
+
+    /// Estimated tokens logged since the last summary
+    #[serde(default)]
+    pub unsummarized_tokens: usize,
+
+    /// State saved by the last [`SmartAgent`](crate::SmartAgent) that used
+    /// this session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent_state: Option<Value>,
+}
+
+/// One version of a session's summary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    /// Unique identifier for this summary
+    pub id: String,
+
+    /// Session this summary belongs to
+    pub session_id: String,
+
+    /// Summary text
+    pub content: String,
+
+    /// Model that wrote the summary, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// First trace summarized in this run
+    ///
+    /// Incremental summaries also cover everything before this trace through
+    /// the previous summary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_trace_id: Option<String>,
+
+    /// Last trace covered by this summary
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_trace_id: Option<String>,
+
+    /// When this summary was generated
+    pub created_at: DateTime<Utc>,
+}
+
+impl SessionSummary {
+    /// Create a new summary for a session
+    pub fn new(session_id: String, content: String) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            session_id,
+            content,
+            model: None,
+            first_trace_id: None,
+            last_trace_id: None,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Parse a timestamp as written by SQLite's `datetime('now')`
+///
+/// Falls back to RFC 3339 so rows written by other tools still load.
+pub(crate) fn parse_sqlite_datetime(s: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .map(|dt| dt.and_utc())
+        .ok()
+        .or_else(|| {
+            DateTime::parse_from_rfc3339(s)
+                .map(|dt| dt.with_timezone(&Utc))
+                .ok()
+        })
+}
@@ -1,21 +1,134 @@
 //! SmartAgent wrapper that adds automatic history recall and summarization
 
-use crate::{AgentHistory, Result};
+use crate::{
+    AgentHistory, ContextBuilder, Error, RecallOptions, Result, Retriever,
+    RetryPolicy, Role, SessionSummary,
+};
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt, stream};
 use rig::{
     agent::Agent,
-    completion::{Chat, CompletionModel, Message},
+    completion::{
+        Chat, CompletionError, CompletionModel, Message, PromptError,
+    },
 };
-use serde_json::json;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
 use std::collections::HashMap;
-use std::time::Instant;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// Boxed future produced by a type-erased summarizer
+type SummaryFuture = Pin<Box<dyn Future<Output = Result<String>> + Send>>;
+
+/// Summarizes a session without tying SmartAgent to the summarizer's model
+type Summarizer = Arc<dyn Fn(AgentHistory) -> SummaryFuture + Send + Sync>;
+
+/// Boxed future produced by a type-erased fallback agent
+type FallbackFuture = Pin<
+    Box<dyn Future<Output = std::result::Result<String, PromptError>> + Send>,
+>;
+
+/// Calls the fallback agent without tying SmartAgent to its model
+type Fallback =
+    Arc<dyn Fn(String, Vec<Message>) -> FallbackFuture + Send + Sync>;
+
+/// Response chunks produced by a streamer
+///
+/// See [`SmartAgent::with_streamer`].
+pub type ChunkStream = Pin<Box<dyn Stream<Item = Result<String>> + Send>>;
+
+/// Response chunks of one [`SmartAgent::chat_stream`] turn
+pub type ChatStream<'a> =
+    Pin<Box<dyn Stream<Item = Result<String>> + Send + 'a>>;
+
+/// Streams a response to a prompt and chat history
+type Streamer = Arc<dyn Fn(String, Vec<Message>) -> ChunkStream + Send + Sync>;
+
+/// Content and metadata of an interrupted streaming reply, held until it
+/// can be logged in order
+type PendingReply = Arc<Mutex<Option<(String, HashMap<String, Value>)>>>;
+
+/// When SmartAgent summarizes the session automatically
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryTrigger {
+    /// Every `n` chat turns of this SmartAgent
+    Turns(usize),
+
+    /// Once `n` estimated tokens were logged since the last summary
+    ///
+    /// Tokens are counted by the history's token counter (see
+    /// [`AgentHistory::with_token_counter`]) and stored with the session,
+    /// so the count carries over across restarts.
+    Tokens(usize),
+
+    /// Once `n` characters were logged since the last summary
+    Chars(usize),
+}
+
+impl Default for SummaryTrigger {
+    fn default() -> Self {
+        SummaryTrigger::Turns(20)
+    }
+}
+
+/// State of session summarization
+#[derive(Debug, Clone, Default)]
+pub struct SummaryStatus {
+    /// Whether a background summary is still being generated
+    pub pending: bool,
+
+    /// Error of the last summarization attempt, cleared on success
+    pub last_error: Option<String>,
+
+    /// When the last summary was stored
+    pub last_summarized_at: Option<DateTime<Utc>>,
+
+    /// ID of the last trace covered by the session's summary
+    pub checkpoint: Option<String>,
+}
+
+/// SmartAgent state saved with the session after every turn
+///
+/// Fields missing from an older saved state keep the values the SmartAgent
+/// was constructed with.
+#[derive(Debug, Serialize, Deserialize)]
+struct AgentState {
+    turn_count: usize,
+    recall_top_k: usize,
+    recent_turns: usize,
+    inject_summary: bool,
+    related_summaries: usize,
+    summary_trigger: SummaryTrigger,
+    retriever: Retriever,
+    recall_options: RecallOptions,
+    context_budget: usize,
+}
 
 /// A smart agent wrapper that automatically manages persistent memory
 pub struct SmartAgent<M: CompletionModel> {
-    agent: Agent<M>,
+    agent: Arc<Agent<M>>,
     history: AgentHistory,
+    retriever: Retriever,
+    recall_options: RecallOptions,
+    context_builder: ContextBuilder,
     recall_top_k: usize,
-    summarize_every: usize,
+    recent_turns: usize,
+    inject_summary: bool,
+    related_summaries: usize,
+    summary_trigger: SummaryTrigger,
     turn_count: usize,
+    summarizer: Option<Summarizer>,
+    summary_task: Option<JoinHandle<Result<String>>>,
+    summary_status: Arc<Mutex<SummaryStatus>>,
+    streamer: Option<Streamer>,
+    pending_reply: PendingReply,
+    retry_policy: RetryPolicy,
+    fallback: Option<Fallback>,
 }
 
 impl<M: CompletionModel + 'static> SmartAgent<M> {
@@ -45,12 +158,88 @@ impl<M: CompletionModel + 'static> SmartAgent<M> {
     /// ```
     pub fn new(agent: Agent<M>, history: AgentHistory) -> Self {
         Self {
-            agent,
+            agent: Arc::new(agent),
             history,
+            retriever: Retriever::default(),
+            recall_options: RecallOptions::default(),
+            context_builder: ContextBuilder::default(),
             recall_top_k: 4,
-            summarize_every: 20,
+            recent_turns: 0,
+            inject_summary: true,
+            related_summaries: 0,
+            summary_trigger: SummaryTrigger::default(),
             turn_count: 0,
+            summarizer: None,
+            summary_task: None,
+            summary_status: Arc::default(),
+            streamer: None,
+            pending_reply: PendingReply::default(),
+            retry_policy: RetryPolicy::none(),
+            fallback: None,
+        }
+    }
+
+    /// Create a SmartAgent that picks up where the session left off
+    ///
+    /// Restores the turn count, summary status and configuration saved by the
+    /// last SmartAgent that chatted in this session. Builder methods called
+    /// afterwards override the restored configuration. Sessions logged
+    /// without a SmartAgent count one turn per user and assistant trace pair.
+    ///
+    /// # Arguments
+    /// * `agent` - The base Rig agent to wrap
+    /// * `history` - AgentHistory instance for persistence
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use agentsmith::{AgentHistory, SmartAgent};
+    /// use rig::providers::openai;
+    ///
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = openai::Client::new("your-api-key");
+    /// let history = AgentHistory::new("agent.db", Some("session-1")).await?;
+    ///
+    /// let mut smart_agent =
+    ///     SmartAgent::resume(client.agent("gpt-4").build(), history).await?;
+    /// println!("Resuming at turn {}", smart_agent.turn_count());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn resume(
+        agent: Agent<M>,
+        history: AgentHistory,
+    ) -> Result<Self> {
+        let mut smart_agent = Self::new(agent, history);
+
+        let session_id = smart_agent.history.session_id().to_string();
+        let Some(session) =
+            smart_agent.history.get_session(&session_id).await?
+        else {
+            return Ok(smart_agent);
+        };
+
+        let latest = smart_agent.history.latest_summary(&session_id).await?;
+        {
+            let mut status = smart_agent
+                .summary_status
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            status.last_summarized_at = latest.map(|s| s.created_at);
+            status.checkpoint = session.summary_checkpoint;
         }
+
+        match session.agent_state {
+            Some(Value::Object(saved)) => {
+                let mut state = serde_json::to_value(smart_agent.state())?;
+                if let Value::Object(fields) = &mut state {
+                    fields.extend(saved);
+                }
+                smart_agent.apply_state(serde_json::from_value(state)?);
+            }
+            _ => smart_agent.turn_count = session.trace_count / 2,
+        }
+
+        Ok(smart_agent)
     }
 
     /// Set the number of relevant past traces to recall (default: 4)
@@ -59,9 +248,235 @@ impl<M: CompletionModel + 'static> SmartAgent<M> {
         self
     }
 
+    /// Include the last `n` turns of the session in every prompt (default: 0)
+    ///
+    /// Turns are sent as regular chat history in chronological order, so the
+    /// conversation stays coherent across process restarts. Recalled traces
+    /// that are already among these turns are not repeated.
+    pub fn with_recent_turns(mut self, n: usize) -> Self {
+        self.recent_turns = n;
+        self
+    }
+
+    /// Set the strategy used to recall past traces (default: full-text search)
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, HashEmbedder, HybridConfig, Retriever, SmartAgent};
+    /// # use rig::providers::openai;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let agent = openai::Client::new("your-api-key").agent("gpt-4").build();
+    /// let history = AgentHistory::new("agent.db", None)
+    ///     .await?
+    ///     .with_embedder(HashEmbedder::default());
+    ///
+    /// let smart_agent = SmartAgent::new(agent, history).with_retriever(
+    ///     Retriever::Hybrid(HybridConfig::default().with_vector_weight(2.0)),
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_retriever(mut self, retriever: Retriever) -> Self {
+        self.retriever = retriever;
+        self
+    }
+
+    /// Whether to include the session's latest summary in every prompt
+    /// (default: true)
+    pub fn with_session_summary(mut self, inject: bool) -> Self {
+        self.inject_summary = inject;
+        self
+    }
+
+    /// Include summaries of up to `n` other sessions that match the user's
+    /// message (default: 0)
+    pub fn with_related_summaries(mut self, n: usize) -> Self {
+        self.related_summaries = n;
+        self
+    }
+
+    /// Set how recalled traces are filtered (default: no filtering)
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, Dedupe, RecallOptions, SmartAgent};
+    /// # use rig::providers::openai;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let agent = openai::Client::new("your-api-key").agent("gpt-4").build();
+    /// # let history = AgentHistory::new("agent.db", None).await?;
+    /// let smart_agent = SmartAgent::new(agent, history).with_recall_options(
+    ///     RecallOptions::default()
+    ///         .with_exclude_recent(4)
+    ///         .with_dedupe(Dedupe::Normalized)
+    ///         .with_pair_answers(true),
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_recall_options(mut self, options: RecallOptions) -> Self {
+        self.recall_options = options;
+        self
+    }
+
+    /// Set the token budget for injected context (default: 4000)
+    pub fn with_context_budget(mut self, max_tokens: usize) -> Self {
+        let builder = std::mem::take(&mut self.context_builder);
+        self.context_builder = builder.with_max_tokens(max_tokens);
+        self
+    }
+
+    /// Set how injected context is fitted into the token budget
+    pub fn with_context_builder(mut self, builder: ContextBuilder) -> Self {
+        self.context_builder = builder;
+        self
+    }
+
     /// Set how often to auto-summarize the session (default: every 20 turns)
     pub fn with_summarize_every(mut self, n: usize) -> Self {
-        self.summarize_every = n;
+        self.summary_trigger = SummaryTrigger::Turns(n);
+        self
+    }
+
+    /// Set when to auto-summarize the session
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, SmartAgent, SummaryTrigger};
+    /// # use rig::providers::openai;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let agent = openai::Client::new("your-api-key").agent("gpt-4").build();
+    /// # let history = AgentHistory::new("agent.db", None).await?;
+    /// let smart_agent = SmartAgent::new(agent, history)
+    ///     .with_summary_trigger(SummaryTrigger::Tokens(3000));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_summary_trigger(mut self, trigger: SummaryTrigger) -> Self {
+        self.summary_trigger = trigger;
+        self
+    }
+
+    /// Summarize in the background with a separate agent
+    ///
+    /// Periodic summaries always run on a spawned task, so `chat` never waits
+    /// for them; a new summary is not started while the previous one is
+    /// pending. Without a summarizer, the main agent writes them.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, SmartAgent};
+    /// # use rig::providers::openai;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = openai::Client::new("your-api-key");
+    /// let history = AgentHistory::new("agent.db", None).await?;
+    ///
+    /// let mut smart_agent =
+    ///     SmartAgent::new(client.agent("gpt-4").build(), history)
+    ///         .with_summarizer(client.agent("gpt-4o-mini").build());
+    ///
+    /// smart_agent.chat("Hello!").await?;
+    ///
+    /// // Let a pending summary finish before exiting
+    /// smart_agent.wait_for_summary().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_summarizer<S: CompletionModel + 'static>(
+        mut self,
+        summarizer: Agent<S>,
+    ) -> Self {
+        let summarizer = Arc::new(summarizer);
+        self.summarizer = Some(Arc::new(move |history: AgentHistory| {
+            let summarizer = summarizer.clone();
+            Box::pin(
+                async move { history.summarize_session(&summarizer).await },
+            )
+        }));
+        self
+    }
+
+    /// Set how failed agent calls are retried (default: no retries)
+    ///
+    /// Applies to [`SmartAgent::chat`], and to [`SmartAgent::chat_stream`]
+    /// without a streamer. Responses from a streamer set with
+    /// [`SmartAgent::with_streamer`] are neither retried nor handed to the
+    /// fallback; a failing stream yields its error.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, RetryPolicy, SmartAgent};
+    /// # use rig::providers::openai;
+    /// # use std::time::Duration;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = openai::Client::new("your-api-key");
+    /// let history = AgentHistory::new("agent.db", None).await?;
+    ///
+    /// let smart_agent =
+    ///     SmartAgent::new(client.agent("gpt-4").build(), history)
+    ///         .with_retry_policy(
+    ///             RetryPolicy::default()
+    ///                 .with_backoff(Duration::from_secs(1), 2.0),
+    ///         )
+    ///         .with_fallback(client.agent("gpt-4o-mini").build());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Call `fallback` when the agent still fails after its retries
+    ///
+    /// The fallback is retried under the same policy. Every attempt is
+    /// listed in the `attempts` metadata of the logged trace; `retries`
+    /// adds up the retries of both agents, and `fallback` tells whether the
+    /// fallback was called. Like the retry policy, it isn't used for
+    /// responses from a streamer.
+    pub fn with_fallback<F: CompletionModel + 'static>(
+        mut self,
+        fallback: Agent<F>,
+    ) -> Self {
+        let fallback = Arc::new(fallback);
+        self.fallback = Some(Arc::new(move |prompt, history| {
+            let fallback = fallback.clone();
+            Box::pin(async move { fallback.chat(&prompt, history).await })
+        }));
+        self
+    }
+
+    /// Stream responses for [`SmartAgent::chat_stream`] with `streamer`
+    ///
+    /// Rig has no streaming completions yet, so this is where a provider's
+    /// streaming API is plugged in. The streamer gets the user's message and
+    /// the chat history SmartAgent assembled for it, and returns the
+    /// response in chunks. Streamed responses bypass the retry policy and
+    /// the fallback agent.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use agentsmith::{AgentHistory, ChunkStream, MemoryStore, SmartAgent};
+    /// # use rig::{agent::Agent, completion::CompletionModel};
+    /// # async fn example<M: CompletionModel + 'static>(
+    /// #     agent: Agent<M>,
+    /// # ) -> Result<(), Box<dyn std::error::Error>> {
+    /// # let history = AgentHistory::with_store(MemoryStore::new(), None).await?;
+    /// let smart_agent =
+    ///     SmartAgent::new(agent, history).with_streamer(|prompt, _history| {
+    ///         let words: Vec<agentsmith::Result<String>> = prompt
+    ///             .split_inclusive(' ')
+    ///             .map(|word| Ok(word.to_string()))
+    ///             .collect();
+    ///         Box::pin(futures::stream::iter(words)) as ChunkStream
+    ///     });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_streamer<F>(mut self, streamer: F) -> Self
+    where
+        F: Fn(String, Vec<Message>) -> ChunkStream + Send + Sync + 'static,
+    {
+        self.streamer = Some(Arc::new(streamer));
         self
     }
 
@@ -69,42 +484,234 @@ impl<M: CompletionModel + 'static> SmartAgent<M> {
     ///
     /// This method:
     /// 1. Searches history for relevant past traces
-    /// 2. Injects them as context
+    /// 2. Injects the session summary, recent turns and as many recalled
+    ///    traces as fit the context budget
     /// 3. Sends the user message
-    /// 4. Logs the response with metadata
+    /// 4. Logs the response with metadata, or an `error` trace with the
+    ///    error kind and message if the agent call fails
     /// 5. Periodically triggers summarization
     pub async fn chat(&mut self, user_input: &str) -> Result<String> {
         let start = Instant::now();
+        let context_messages = self.begin_turn(user_input).await?;
 
-        // 1. Search for relevant past traces
-        let relevant_traces =
-            self.history.search(user_input, self.recall_top_k, false).await?;
-
-        // 2. Build context with relevant past experiences
-        let mut context_messages = Vec::new();
-
-        if !relevant_traces.is_empty() {
-            let mut recall_context =
-                String::from("Relevant past experiences:\n\n");
-            for (i, trace) in relevant_traces.iter().enumerate() {
-                recall_context.push_str(&format!(
-                    "{}. [{}] {}: {}\n",
-                    i + 1,
-                    trace.created_at.format("%Y-%m-%d %H:%M"),
-                    trace.role,
-                    trace.content
-                ));
+        // 4. Call the underlying agent, logging failures as error traces
+        let call = self.call_agent(user_input, context_messages).await;
+        let response = match call.result {
+            Ok(response) => response,
+            Err(e) => {
+                let mut metadata = failure_metadata(
+                    error_kind(&e),
+                    &e.to_string(),
+                    start.elapsed(),
+                );
+                metadata.extend(attempt_metadata(&call.attempts));
+                log_error_trace(&self.history, &e.to_string(), metadata).await;
+                return Err(Error::Rig(e.to_string()));
             }
+        };
 
-            context_messages.push(Message {
-                role: "system".to_string(),
-                content: recall_context,
-            });
-        }
+        let metadata = attempt_metadata(&call.attempts);
+        self.finish_turn(&response, start.elapsed(), metadata).await?;
+
+        Ok(response)
+    }
+
+    /// Chat with the agent, yielding the response in chunks as they arrive
+    ///
+    /// The user turn is logged before this returns. The assistant trace is
+    /// logged once the stream ends. If the stream fails, what was received
+    /// so far is logged with `success: false` and `interrupted: true`; if
+    /// it is dropped first, the same happens ahead of the next turn (or when
+    /// the SmartAgent is dropped). Chunks come from the streamer set with
+    /// [`SmartAgent::with_streamer`], or else the whole response arrives as
+    /// a single chunk.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use agentsmith::{AgentHistory, SmartAgent};
+    /// # use futures::StreamExt;
+    /// # use rig::providers::openai;
+    /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let agent = openai::Client::new("your-api-key").agent("gpt-4").build();
+    /// # let history = AgentHistory::new("agent.db", None).await?;
+    /// let mut smart_agent = SmartAgent::new(agent, history);
+    ///
+    /// let mut stream = smart_agent.chat_stream("Tell me a story").await?;
+    /// while let Some(chunk) = stream.next().await {
+    ///     print!("{}", chunk?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn chat_stream(
+        &mut self,
+        user_input: &str,
+    ) -> Result<ChatStream<'_>> {
+        let start = Instant::now();
+        let context_messages = self.begin_turn(user_input).await?;
+
+        let partial = PartialReply {
+            history: self.history.clone(),
+            pending: self.pending_reply.clone(),
+            content: String::new(),
+            start,
+            armed: true,
+        };
+        let chunks = self.streamer.as_ref().map(|streamer| {
+            streamer(user_input.to_string(), context_messages.clone())
+        });
+        let turn = StreamTurn {
+            smart_agent: self,
+            user_input: user_input.to_string(),
+            context_messages,
+            chunks,
+            partial,
+            error_kind: "stream",
+            attempts: Vec::new(),
+            done: false,
+        };
+
+        Ok(Box::pin(stream::unfold(turn, |mut turn| async move {
+            if turn.done {
+                return None;
+            }
+
+            let next = match &mut turn.chunks {
+                Some(chunks) => chunks.next().await,
+                None => {
+                    // Without a streamer the whole reply is the only chunk
+                    let call = turn
+                        .smart_agent
+                        .call_agent(
+                            &turn.user_input,
+                            std::mem::take(&mut turn.context_messages),
+                        )
+                        .await;
+                    turn.attempts = call.attempts;
+                    let reply = call.result.map_err(|e| {
+                        turn.error_kind = error_kind(&e);
+                        Error::Rig(e.to_string())
+                    });
+                    turn.chunks = Some(Box::pin(stream::empty()));
+                    Some(reply)
+                }
+            };
+
+            match next {
+                Some(Ok(chunk)) => {
+                    turn.partial.content.push_str(&chunk);
+                    Some((Ok(chunk), turn))
+                }
+                Some(Err(e)) => {
+                    turn.done = true;
+                    let mut metadata = failure_metadata(
+                        turn.error_kind,
+                        &e.to_string(),
+                        turn.partial.start.elapsed(),
+                    );
+                    metadata.extend(attempt_metadata(&turn.attempts));
+
+                    // Keep whatever arrived before the error
+                    if turn.partial.content.is_empty() {
+                        log_error_trace(
+                            &turn.partial.history,
+                            &e.to_string(),
+                            metadata,
+                        )
+                        .await;
+                    } else {
+                        metadata
+                            .insert("interrupted".to_string(), json!(true));
+                        if let Err(log_error) =
+                            turn.partial.log(metadata).await
+                        {
+                            tracing::warn!(
+                                "Failed to log partial reply: {}",
+                                log_error
+                            );
+                        }
+                    }
+                    turn.partial.armed = false;
+                    Some((Err(e), turn))
+                }
+                None => {
+                    turn.done = true;
+                    let duration = turn.partial.start.elapsed();
+                    let metadata = attempt_metadata(&turn.attempts);
+
+                    // Until the reply is stored, dropping the stream still
+                    // logs it as interrupted
+                    let logged = turn
+                        .smart_agent
+                        .log_reply(&turn.partial.content, duration, metadata)
+                        .await;
+                    match logged {
+                        Ok(()) => {
+                            turn.partial.armed = false;
+                            turn.smart_agent.after_turn().await;
+                            None
+                        }
+                        Err(e) => Some((Err(e), turn)),
+                    }
+                }
+            }
+        })))
+    }
+
+    /// Recall context and log the user turn, returning the chat history to
+    /// send with it
+    async fn begin_turn(&mut self, user_input: &str) -> Result<Vec<Message>> {
+        self.log_pending_reply().await;
+
+        // 1. Search for relevant past traces
+        let mut relevant_traces = self
+            .retriever
+            .recall(
+                &self.history,
+                user_input,
+                self.recall_top_k,
+                &self.recall_options,
+            )
+            .await?;
+
+        // Recent turns are sent verbatim, so don't recall them twice
+        // Failed calls are logged as error traces, which aren't chat turns
+        let mut recent_traces = if self.recent_turns > 0 {
+            self.history.recent(self.recent_turns).await?
+        } else {
+            Vec::new()
+        };
+        recent_traces.retain(|trace| trace.role != Role::Error);
+        relevant_traces.retain(|trace| {
+            !recent_traces.iter().any(|recent| {
+                recent.id == trace.id
+                    || (recent.role == trace.role
+                        && recent.content == trace.content)
+            })
+        });
+
+        let summary = if self.inject_summary {
+            self.history.latest_summary(self.history.session_id()).await?
+        } else {
+            None
+        };
+        let related = self.related_summaries(user_input).await?;
+
+        // 2. Fit summaries, recent turns and past experiences into the
+        //    context budget
+        let reserved = self.context_builder.token_counter().count(user_input);
+        let window = self.context_builder.build(
+            summary.as_ref().map(|s| s.content.as_str()),
+            &related,
+            &recent_traces,
+            &relevant_traces,
+            reserved,
+        );
+        let context_messages = window.to_messages();
 
         // 3. Append current user message
         let user_message = Message {
-            role: "user".to_string(),
+            role: Role::User.to_string(),
             content: user_input.to_string(),
         };
 
@@ -112,26 +719,80 @@ impl<M: CompletionModel + 'static> SmartAgent<M> {
         let mut user_metadata = HashMap::new();
         user_metadata.insert(
             "recalled_traces".to_string(),
-            json!(relevant_traces.len()),
+            json!(window.recalled.len()),
         );
+        user_metadata
+            .insert("recent_turns".to_string(), json!(window.recent.len()));
+        user_metadata.insert(
+            "summary_injected".to_string(),
+            json!(window.summary.is_some()),
+        );
+        user_metadata.insert(
+            "related_summaries".to_string(),
+            json!(window.related.len()),
+        );
+        user_metadata
+            .insert("context_tokens".to_string(), json!(window.tokens_used));
         self.history.log_turn(&user_message, user_metadata).await?;
 
-        // 4. Call the underlying agent
-        let response = self
-            .agent
-            .chat(user_input, context_messages)
-            .await
-            .map_err(|e| crate::Error::Rig(e.to_string()))?;
+        Ok(context_messages)
+    }
+
+    /// Call the agent under the retry policy, then the fallback if it
+    /// still failed
+    async fn call_agent(
+        &self,
+        user_input: &str,
+        context_messages: Vec<Message>,
+    ) -> AgentCall {
+        let mut attempts = Vec::new();
+        let mut result =
+            retry(&self.retry_policy, "primary", &mut attempts, || {
+                self.agent.chat(user_input, context_messages.clone())
+            })
+            .await;
+
+        if result.is_err()
+            && let Some(fallback) = &self.fallback
+        {
+            result =
+                retry(&self.retry_policy, "fallback", &mut attempts, || {
+                    fallback(user_input.to_string(), context_messages.clone())
+                })
+                .await;
+        }
 
-        let duration = start.elapsed();
+        AgentCall { result, attempts }
+    }
 
+    /// Log the assistant's response and run the per-turn bookkeeping
+    ///
+    /// Only logging the response can fail the turn. Once it is stored, the
+    /// caller must get it back, so bookkeeping errors are only warned about.
+    async fn finish_turn(
+        &mut self,
+        response: &str,
+        duration: Duration,
+        metadata: HashMap<String, Value>,
+    ) -> Result<()> {
+        self.log_reply(response, duration, metadata).await?;
+        self.after_turn().await;
+        Ok(())
+    }
+
+    /// Log the assistant's response
+    async fn log_reply(
+        &self,
+        response: &str,
+        duration: Duration,
+        mut metadata: HashMap<String, Value>,
+    ) -> Result<()> {
         // 5. Log assistant response with metadata
         let assistant_message = Message {
-            role: "assistant".to_string(),
-            content: response.clone(),
+            role: Role::Assistant.to_string(),
+            content: response.to_string(),
         };
 
-        let mut metadata = HashMap::new();
         metadata
             .insert("duration_ms".to_string(), json!(duration.as_millis()));
         metadata.insert("success".to_string(), json!(true));
@@ -141,15 +802,34 @@ impl<M: CompletionModel + 'static> SmartAgent<M> {
         metadata.insert("tokens_used".to_string(), json!(null));
 
         self.history.log_turn(&assistant_message, metadata).await?;
+        Ok(())
+    }
 
-        // 6. Increment turn count and check if we should summarize
+    /// Count the finished turn, save the state and summarize if due
+    async fn after_turn(&mut self) {
+        // 6. Increment turn count, save it and check if we should summarize
         self.turn_count += 1;
-        if self.turn_count.is_multiple_of(self.summarize_every) {
-            // Trigger background summarization (we could make this async in the background)
-            let _ = self.history.summarize_session(&self.agent).await;
+        if let Err(e) = self.save_state().await {
+            tracing::warn!("Failed to save agent state: {}", e);
+        }
+        // The reply is stored; a failed check only delays the summary
+        match self.should_summarize().await {
+            Ok(true) => self.start_summary(),
+            Ok(false) => {}
+            Err(e) => tracing::warn!("Failed to check summary trigger: {}", e),
         }
+    }
 
-        Ok(response)
+    /// Log a reply whose stream was dropped, before anything else is logged
+    async fn log_pending_reply(&self) {
+        let pending = self
+            .pending_reply
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some((content, metadata)) = pending {
+            log_interrupted(&self.history, content, metadata).await;
+        }
     }
 
     /// Get a reference to the underlying agent
@@ -158,8 +838,11 @@ impl<M: CompletionModel + 'static> SmartAgent<M> {
     }
 
     /// Get a mutable reference to the underlying agent
-    pub fn agent_mut(&mut self) -> &mut Agent<M> {
-        &mut self.agent
+    ///
+    /// Returns `None` while a background summary is using the agent; call
+    /// [`SmartAgent::wait_for_summary`] first to be sure to get it.
+    pub fn agent_mut(&mut self) -> Option<&mut Agent<M>> {
+        Arc::get_mut(&mut self.agent)
     }
 
     /// Get a reference to the history
@@ -172,8 +855,418 @@ impl<M: CompletionModel + 'static> SmartAgent<M> {
         self.turn_count
     }
 
-    /// Manually trigger session summarization
+    /// Manually trigger session summarization, waiting for the result
     pub async fn summarize(&self) -> Result<String> {
-        self.history.summarize_session(&self.agent).await
+        let result = match &self.summarizer {
+            Some(summarizer) => summarizer(self.history.clone()).await,
+            None => self.history.summarize_session(&*self.agent).await,
+        };
+        record_summary(&self.summary_status, &self.history, &result).await;
+        result
+    }
+
+    /// Get the state of session summarization
+    pub fn summary_status(&self) -> SummaryStatus {
+        let mut status = self
+            .summary_status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        status.pending =
+            self.summary_task.as_ref().is_some_and(|task| !task.is_finished());
+        status
+    }
+
+    /// Wait for a background summary to finish
+    ///
+    /// Returns the new summary, or `None` if no summary was running. Pending
+    /// summaries keep running after the SmartAgent is dropped, so call this
+    /// (or [`SmartAgent::cancel_summary`]) before shutting down the runtime.
+    pub async fn wait_for_summary(&mut self) -> Result<Option<String>> {
+        let Some(task) = self.summary_task.take() else {
+            return Ok(None);
+        };
+
+        task.await
+            .map_err(|e| Error::Other(format!("Summary task failed: {}", e)))?
+            .map(Some)
+    }
+
+    /// Abort a background summary, returning whether one was running
+    pub fn cancel_summary(&mut self) -> bool {
+        match self.summary_task.take() {
+            Some(task) if !task.is_finished() => {
+                task.abort();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Snapshot of the state saved with the session
+    fn state(&self) -> AgentState {
+        AgentState {
+            turn_count: self.turn_count,
+            recall_top_k: self.recall_top_k,
+            recent_turns: self.recent_turns,
+            inject_summary: self.inject_summary,
+            related_summaries: self.related_summaries,
+            summary_trigger: self.summary_trigger,
+            retriever: self.retriever.clone(),
+            recall_options: self.recall_options.clone(),
+            context_budget: self.context_builder.max_tokens(),
+        }
+    }
+
+    /// Save the current state with the session
+    async fn save_state(&self) -> Result<()> {
+        let state = serde_json::to_value(self.state())?;
+        self.history
+            .store()
+            .set_agent_state(self.history.session_id(), &state)
+            .await
+    }
+
+    /// Restore a saved state
+    fn apply_state(&mut self, state: AgentState) {
+        self.turn_count = state.turn_count;
+        self.recall_top_k = state.recall_top_k;
+        self.recent_turns = state.recent_turns;
+        self.inject_summary = state.inject_summary;
+        self.related_summaries = state.related_summaries;
+        self.summary_trigger = state.summary_trigger;
+        self.retriever = state.retriever;
+        self.recall_options = state.recall_options;
+        self.context_builder =
+            self.context_builder.clone().with_max_tokens(state.context_budget);
+    }
+
+    /// Find the best matching summary of up to `related_summaries` other
+    /// sessions
+    async fn related_summaries(
+        &self,
+        user_input: &str,
+    ) -> Result<Vec<SessionSummary>> {
+        if self.related_summaries == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Sessions can have several matching versions; keep the best one
+        let candidates = self
+            .history
+            .search_summaries(user_input, self.related_summaries * 4)
+            .await?;
+        let mut related: Vec<SessionSummary> = Vec::new();
+        for summary in candidates {
+            if summary.session_id != self.history.session_id()
+                && related.iter().all(|s| s.session_id != summary.session_id)
+            {
+                related.push(summary);
+            }
+        }
+        related.truncate(self.related_summaries);
+
+        Ok(related)
+    }
+
+    /// Check the summary trigger against the turn count or the session's
+    /// stored unsummarized size
+    async fn should_summarize(&self) -> Result<bool> {
+        let threshold = match self.summary_trigger {
+            SummaryTrigger::Turns(n) => {
+                return Ok(self.turn_count.is_multiple_of(n));
+            }
+            SummaryTrigger::Tokens(n) | SummaryTrigger::Chars(n) => n,
+        };
+
+        let Some(session) =
+            self.history.get_session(self.history.session_id()).await?
+        else {
+            return Ok(false);
+        };
+
+        Ok(match self.summary_trigger {
+            SummaryTrigger::Tokens(_) => {
+                session.unsummarized_tokens >= threshold
+            }
+            _ => session.unsummarized_chars >= threshold,
+        })
+    }
+
+    /// Summarize the session on a spawned task, with the summarizer if one
+    /// is set and the main agent otherwise
+    fn start_summary(&mut self) {
+        if self.summary_task.as_ref().is_some_and(|task| !task.is_finished()) {
+            tracing::debug!("Summary still pending, skipping");
+            return;
+        }
+
+        let status = self.summary_status.clone();
+        let history = self.history.clone();
+        let summary: SummaryFuture = match &self.summarizer {
+            Some(summarizer) => summarizer(self.history.clone()),
+            None => {
+                let agent = self.agent.clone();
+                let history = self.history.clone();
+                Box::pin(
+                    async move { history.summarize_session(&*agent).await },
+                )
+            }
+        };
+        self.summary_task = Some(tokio::spawn(async move {
+            let result = summary.await;
+            record_summary(&status, &history, &result).await;
+            result
+        }));
+    }
+}
+
+/// Store the outcome of a summarization attempt
+async fn record_summary(
+    status: &Mutex<SummaryStatus>,
+    history: &AgentHistory,
+    result: &Result<String>,
+) {
+    let checkpoint = match result {
+        Ok(_) => history
+            .get_session(history.session_id())
+            .await
+            .ok()
+            .flatten()
+            .and_then(|session| session.summary_checkpoint),
+        Err(_) => None,
+    };
+
+    let mut status = status.lock().unwrap_or_else(PoisonError::into_inner);
+    match result {
+        Ok(_) => {
+            status.last_error = None;
+            status.last_summarized_at = Some(Utc::now());
+            status.checkpoint = checkpoint;
+        }
+        Err(e) => {
+            tracing::warn!("Session summarization failed: {}", e);
+            status.last_error = Some(e.to_string());
+        }
+    }
+}
+
+/// Short name of the kind of error an agent call failed with
+fn error_kind(error: &PromptError) -> &'static str {
+    match error {
+        PromptError::CompletionError(e) => match e {
+            CompletionError::HttpError(_) => "http",
+            CompletionError::JsonError(_) => "json",
+            CompletionError::RequestError(_) => "request",
+            CompletionError::ResponseError(_) => "response",
+            CompletionError::ProviderError(_) => "provider",
+        },
+        PromptError::ToolError(_) => "tool",
+    }
+}
+
+/// Metadata of a trace recording a failed agent call
+fn failure_metadata(
+    kind: &str,
+    message: &str,
+    duration: Duration,
+) -> HashMap<String, Value> {
+    let mut metadata = HashMap::new();
+    metadata.insert("duration_ms".to_string(), json!(duration.as_millis()));
+    metadata.insert("success".to_string(), json!(false));
+    metadata.insert("error_kind".to_string(), json!(kind));
+    metadata.insert("error".to_string(), json!(message));
+    metadata
+}
+
+/// Metadata describing the attempts made for one response
+///
+/// `retries` counts repeated calls to the same agent; switching to the
+/// fallback is reported by `fallback`, not as a retry.
+fn attempt_metadata(attempts: &[Value]) -> HashMap<String, Value> {
+    let fallback = attempts.iter().any(|a| a["agent"] == "fallback");
+    let retries = attempts.iter().filter(|a| a["attempt"] != 1).count();
+
+    let mut metadata = HashMap::new();
+    metadata.insert("retries".to_string(), json!(retries));
+    metadata.insert("fallback".to_string(), json!(fallback));
+    if !attempts.is_empty() {
+        metadata.insert("attempts".to_string(), json!(attempts));
+    }
+    metadata
+}
+
+/// Outcome of an agent call and every attempt made for it
+struct AgentCall {
+    result: std::result::Result<String, PromptError>,
+    attempts: Vec<Value>,
+}
+
+/// Make attempts with `call` until one succeeds or `policy` gives up,
+/// recording each in `attempts`
+async fn retry<F, Fut>(
+    policy: &RetryPolicy,
+    agent: &str,
+    attempts: &mut Vec<Value>,
+    mut call: F,
+) -> std::result::Result<String, PromptError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::result::Result<String, PromptError>>,
+{
+    let mut attempt = 1;
+    loop {
+        let start = Instant::now();
+        let result = call().await;
+
+        let mut record = json!({
+            "agent": agent,
+            "attempt": attempt,
+            "duration_ms": start.elapsed().as_millis(),
+            "success": result.is_ok(),
+        });
+        let error = match result {
+            Ok(response) => {
+                attempts.push(record);
+                return Ok(response);
+            }
+            Err(e) => e,
+        };
+        record["error_kind"] = json!(error_kind(&error));
+        record["error"] = json!(error.to_string());
+        attempts.push(record);
+
+        if attempt >= policy.max_attempts() || !policy.is_retryable(&error) {
+            return Err(error);
+        }
+
+        let delay = policy.backoff(attempt);
+        tracing::debug!(
+            "Attempt {} of the {} agent failed, retrying in {:?}: {}",
+            attempt,
+            agent,
+            delay,
+            error
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Log a failed agent call as an `error` trace
+///
+/// The call's own error matters more than a failure to record it, so the
+/// latter is only logged.
+async fn log_error_trace(
+    history: &AgentHistory,
+    message: &str,
+    metadata: HashMap<String, Value>,
+) {
+    let trace = Message {
+        role: Role::Error.to_string(),
+        content: message.to_string(),
+    };
+    if let Err(e) = history.log_turn(&trace, metadata).await {
+        tracing::warn!("Failed to log agent error: {}", e);
+    }
+}
+
+/// State of one [`SmartAgent::chat_stream`] turn
+struct StreamTurn<'a, M: CompletionModel> {
+    smart_agent: &'a mut SmartAgent<M>,
+    user_input: String,
+    context_messages: Vec<Message>,
+    chunks: Option<ChunkStream>,
+    partial: PartialReply,
+    error_kind: &'static str,
+    attempts: Vec<Value>,
+    done: bool,
+}
+
+/// Assistant response received so far by a streaming turn
+///
+/// Handed to the SmartAgent as a pending interrupted reply if the stream is
+/// dropped before the reply is stored.
+struct PartialReply {
+    history: AgentHistory,
+    pending: PendingReply,
+    content: String,
+    start: Instant,
+    armed: bool,
+}
+
+impl PartialReply {
+    /// Metadata of a trace for an unfinished response
+    fn metadata(&self, interrupted: bool) -> HashMap<String, Value> {
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            "duration_ms".to_string(),
+            json!(self.start.elapsed().as_millis()),
+        );
+        metadata.insert("success".to_string(), json!(false));
+        metadata.insert("interrupted".to_string(), json!(interrupted));
+        metadata
+    }
+
+    /// Log the response received so far
+    async fn log(&self, metadata: HashMap<String, Value>) -> Result<()> {
+        let message = Message {
+            role: Role::Assistant.to_string(),
+            content: self.content.clone(),
+        };
+        self.history.log_turn(&message, metadata).await?;
+        Ok(())
+    }
+}
+
+impl Drop for PartialReply {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        // Drop can't wait, and a spawned write could land after the next
+        // turn, so the SmartAgent logs it before the next user message
+        let metadata = self.metadata(true);
+        let content = std::mem::take(&mut self.content);
+        *self.pending.lock().unwrap_or_else(PoisonError::into_inner) =
+            Some((content, metadata));
+    }
+}
+
+impl<M: CompletionModel> Drop for SmartAgent<M> {
+    fn drop(&mut self) {
+        let pending = self
+            .pending_reply
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        let Some((content, metadata)) = pending else {
+            return;
+        };
+
+        // No further turn will follow, so a spawned write keeps the order
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!("No runtime to log an interrupted reply");
+            return;
+        };
+        let history = self.history.clone();
+        runtime.spawn(async move {
+            log_interrupted(&history, content, metadata).await;
+        });
+    }
+}
+
+/// Log an interrupted streaming reply
+///
+/// Like error traces, a failure to record it is only logged.
+async fn log_interrupted(
+    history: &AgentHistory,
+    content: String,
+    metadata: HashMap<String, Value>,
+) {
+    let message = Message { role: Role::Assistant.to_string(), content };
+    if let Err(e) = history.log_turn(&message, metadata).await {
+        tracing::warn!("Failed to log interrupted reply: {}", e);
     }
 }
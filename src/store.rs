@@ -0,0 +1,191 @@
+//! Storage backends for agent history
+//!
+//! [`AgentHistory`](crate::AgentHistory) talks to its database through the
+//! [`HistoryStore`] trait. [`SqliteStore`] is the default, persistent backend;
+//! [`MemoryStore`] keeps everything in process, which is handy for tests.
+//! With the `postgres` feature, `PostgresStore` shares history between hosts.
+
+mod memory;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod sqlite;
+
+pub use memory::MemoryStore;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+
+use crate::{
+    DuplicateStrategy, ExportFilter, ImportStatus, Result, SearchQuery,
+    Session, SessionSummary, Trace,
+};
+use serde_json::Value;
+use std::{future::Future, pin::Pin};
+
+/// Boxed future returned by [`HistoryStore`] methods
+pub type StoreFuture<'a, T> =
+    Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Persistence operations needed by [`AgentHistory`](crate::AgentHistory)
+///
+/// Traces returned by `recent` and `session_traces` are in chronological
+/// order. Every session referenced by a trace is created with
+/// `ensure_session` or `touch_session` before the trace is inserted.
+pub trait HistoryStore: Send + Sync {
+    /// Create a session if it doesn't exist yet
+    fn ensure_session<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, ()>;
+
+    /// Create a session or bump its `updated_at` to now
+    fn touch_session<'a>(&'a self, session_id: &'a str)
+    -> StoreFuture<'a, ()>;
+
+    /// Add to a session's characters and tokens logged since the last
+    /// summary; negative amounts subtract, stopping at zero
+    fn add_unsummarized<'a>(
+        &'a self,
+        session_id: &'a str,
+        chars: i64,
+        tokens: i64,
+    ) -> StoreFuture<'a, ()>;
+
+    /// Replace the state saved by the SmartAgent using a session
+    fn set_agent_state<'a>(
+        &'a self,
+        session_id: &'a str,
+        state: &'a Value,
+    ) -> StoreFuture<'a, ()>;
+
+    /// Insert a new trace and add its `chars` and `tokens` to the session's
+    /// unsummarized usage, in one transaction
+    fn insert_trace<'a>(
+        &'a self,
+        trace: &'a Trace,
+        chars: i64,
+        tokens: i64,
+    ) -> StoreFuture<'a, ()>;
+
+    /// Store a batch of imported traces in one transaction
+    ///
+    /// `usage` holds the characters and tokens of each trace; those of
+    /// inserted traces are added to their session's unsummarized usage.
+    /// Sessions the traces belong to are created if missing. Returns one
+    /// status per trace, except that with [`DuplicateStrategy::Fail`] the
+    /// statuses end at the first duplicate and only the traces before it
+    /// are stored.
+    fn import_traces<'a>(
+        &'a self,
+        traces: &'a [Trace],
+        usage: &'a [(i64, i64)],
+        duplicates: DuplicateStrategy,
+    ) -> StoreFuture<'a, Vec<ImportStatus>>;
+
+    /// Run a structured search
+    ///
+    /// [`SessionScope::Current`](crate::SessionScope::Current) is resolved to
+    /// a concrete session list by `AgentHistory` before this is called.
+    fn query<'a>(
+        &'a self,
+        query: &'a SearchQuery,
+    ) -> StoreFuture<'a, Vec<Trace>>;
+
+    /// Get the `n` most recent traces of a session
+    fn recent<'a>(
+        &'a self,
+        session_id: &'a str,
+        n: usize,
+    ) -> StoreFuture<'a, Vec<Trace>>;
+
+    /// Get every trace of a session
+    fn session_traces<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Vec<Trace>>;
+
+    /// Get the traces of a session logged after `trace_id`
+    ///
+    /// Traces sharing a timestamp should follow insertion order, so none
+    /// logged right after the checkpoint is skipped. Returns every trace of
+    /// the session when `trace_id` is `None` or no longer exists.
+    fn traces_after<'a>(
+        &'a self,
+        session_id: &'a str,
+        trace_id: Option<&'a str>,
+    ) -> StoreFuture<'a, Vec<Trace>>;
+
+    /// Get up to `limit` traces matching `filter`, ordered by creation time
+    /// and then ID, starting after `cursor`
+    ///
+    /// Passing the last trace of a page as `cursor` fetches the next page.
+    /// [`SessionScope::Current`](crate::SessionScope::Current) is resolved by
+    /// `AgentHistory` before this is called.
+    fn export_traces<'a>(
+        &'a self,
+        filter: &'a ExportFilter,
+        cursor: Option<&'a Trace>,
+        limit: usize,
+    ) -> StoreFuture<'a, Vec<Trace>>;
+
+    /// Get every trace that has an embedding, across all sessions
+    fn embedded_traces(&self) -> StoreFuture<'_, Vec<Trace>>;
+
+    /// Get every trace that has no embedding yet, across all sessions
+    fn unembedded_traces(&self) -> StoreFuture<'_, Vec<Trace>>;
+
+    /// Store an embedding for an existing trace
+    fn set_embedding<'a>(
+        &'a self,
+        trace_id: &'a str,
+        embedding: &'a str,
+    ) -> StoreFuture<'a, ()>;
+
+    /// List all sessions, most recently updated first
+    fn list_sessions(&self) -> StoreFuture<'_, Vec<Session>>;
+
+    /// Get a single session by ID
+    fn get_session<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Option<Session>>;
+
+    /// Delete a session and its traces, returning whether it existed
+    fn delete_session<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, bool>;
+
+    /// Move a session and its traces to a new ID
+    fn rename_session<'a>(
+        &'a self,
+        old_id: &'a str,
+        new_id: &'a str,
+    ) -> StoreFuture<'a, ()>;
+
+    /// Store a new summary version and make it the session's current
+    /// summary, with its last trace as the summary checkpoint
+    fn add_summary<'a>(
+        &'a self,
+        summary: &'a SessionSummary,
+    ) -> StoreFuture<'a, ()>;
+
+    /// Get every summary of a session, oldest first
+    fn list_summaries<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Vec<SessionSummary>>;
+
+    /// Get the newest summary of a session
+    fn latest_summary<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Option<SessionSummary>>;
+
+    /// Full-text search over summaries of all sessions, best match first
+    fn search_summaries<'a>(
+        &'a self,
+        text: &'a str,
+        limit: usize,
+    ) -> StoreFuture<'a, Vec<SessionSummary>>;
+}
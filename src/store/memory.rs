@@ -0,0 +1,593 @@
+//! In-process storage backend, mainly for tests
+
+use super::{HistoryStore, StoreFuture};
+use crate::{
+    CompareOp, DuplicateStrategy, Error, ExportFilter, ImportStatus,
+    MetadataFilter, SearchQuery, Session, SessionScope, SessionSummary, Trace,
+    query::{is_prefix_term, query_terms},
+};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// History store that keeps everything in memory
+///
+/// Clones share the same data. Text search approximates the SQLite store:
+/// terms are OR-ed, terms of three or more characters match as prefixes, and
+/// results are ranked by the number of matching terms. Raw queries are
+/// treated as plain text, since there is no FTS5 engine to parse them.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    sessions: HashMap<String, SessionRow>,
+    traces: Vec<Trace>,
+    summaries: Vec<SessionSummary>,
+}
+
+struct SessionRow {
+    summary: Option<String>,
+    summary_checkpoint: Option<String>,
+    updated_at: DateTime<Utc>,
+    unsummarized_chars: usize,
+    unsummarized_tokens: usize,
+    agent_state: Option<Value>,
+}
+
+impl SessionRow {
+    fn new() -> Self {
+        Self {
+            summary: None,
+            summary_checkpoint: None,
+            updated_at: Utc::now(),
+            unsummarized_chars: 0,
+            unsummarized_tokens: 0,
+            agent_state: None,
+        }
+    }
+
+    /// Add to the unsummarized usage, stopping at zero
+    fn add_unsummarized(&mut self, chars: i64, tokens: i64) {
+        self.unsummarized_chars =
+            self.unsummarized_chars.saturating_add_signed(chars as isize);
+        self.unsummarized_tokens =
+            self.unsummarized_tokens.saturating_add_signed(tokens as isize);
+    }
+}
+
+impl MemoryStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic while holding the lock can't leave the state half-written
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl State {
+    fn session(&self, id: &str) -> Option<Session> {
+        self.sessions.get(id).map(|row| Session {
+            id: id.to_string(),
+            summary: row.summary.clone(),
+            summary_checkpoint: row.summary_checkpoint.clone(),
+            updated_at: row.updated_at,
+            unsummarized_chars: row.unsummarized_chars,
+            unsummarized_tokens: row.unsummarized_tokens,
+            agent_state: row.agent_state.clone(),
+            trace_count: self
+                .traces
+                .iter()
+                .filter(|t| t.session_id == id)
+                .count(),
+        })
+    }
+
+    fn session_summaries(&self, session_id: &str) -> Vec<SessionSummary> {
+        let mut summaries: Vec<SessionSummary> = self
+            .summaries
+            .iter()
+            .filter(|s| s.session_id == session_id)
+            .cloned()
+            .collect();
+        summaries.sort_by_key(|s| s.created_at);
+        summaries
+    }
+
+    fn session_traces(&self, session_id: &str) -> Vec<Trace> {
+        let mut traces: Vec<Trace> = self
+            .traces
+            .iter()
+            .filter(|t| t.session_id == session_id)
+            .cloned()
+            .collect();
+        traces.sort_by_key(|t| t.created_at);
+        traces
+    }
+}
+
+impl HistoryStore for MemoryStore {
+    fn ensure_session<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, ()> {
+        let mut state = self.lock();
+        state
+            .sessions
+            .entry(session_id.to_string())
+            .or_insert_with(SessionRow::new);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn touch_session<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, ()> {
+        let mut state = self.lock();
+        state
+            .sessions
+            .entry(session_id.to_string())
+            .and_modify(|row| row.updated_at = Utc::now())
+            .or_insert_with(SessionRow::new);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn add_unsummarized<'a>(
+        &'a self,
+        session_id: &'a str,
+        chars: i64,
+        tokens: i64,
+    ) -> StoreFuture<'a, ()> {
+        if let Some(row) = self.lock().sessions.get_mut(session_id) {
+            row.add_unsummarized(chars, tokens);
+        }
+        Box::pin(async { Ok(()) })
+    }
+
+    fn set_agent_state<'a>(
+        &'a self,
+        session_id: &'a str,
+        state: &'a Value,
+    ) -> StoreFuture<'a, ()> {
+        if let Some(row) = self.lock().sessions.get_mut(session_id) {
+            row.agent_state = Some(state.clone());
+        }
+        Box::pin(async { Ok(()) })
+    }
+
+    fn insert_trace<'a>(
+        &'a self,
+        trace: &'a Trace,
+        chars: i64,
+        tokens: i64,
+    ) -> StoreFuture<'a, ()> {
+        let mut state = self.lock();
+        let duplicate = state.traces.iter().any(|t| t.id == trace.id);
+        let result = match state.sessions.get_mut(&trace.session_id) {
+            _ if duplicate => {
+                Err(Error::Other(format!("Duplicate trace id: {}", trace.id)))
+            }
+            None => Err(Error::Other(format!(
+                "Session not found: {}",
+                trace.session_id
+            ))),
+            Some(row) => {
+                row.add_unsummarized(chars, tokens);
+                state.traces.push(trace.clone());
+                Ok(())
+            }
+        };
+        Box::pin(async { result })
+    }
+
+    fn import_traces<'a>(
+        &'a self,
+        traces: &'a [Trace],
+        usage: &'a [(i64, i64)],
+        duplicates: DuplicateStrategy,
+    ) -> StoreFuture<'a, Vec<ImportStatus>> {
+        let mut state = self.lock();
+        let mut statuses = Vec::with_capacity(traces.len());
+
+        for (trace, &(chars, tokens)) in traces.iter().zip(usage) {
+            let existing = state.traces.iter().position(|t| t.id == trace.id);
+            let status = match (existing, duplicates) {
+                (Some(_), DuplicateStrategy::Skip) => ImportStatus::Skipped,
+                (Some(_), DuplicateStrategy::Fail) => {
+                    statuses.push(ImportStatus::Duplicate);
+                    break;
+                }
+                (Some(i), DuplicateStrategy::Upsert) => {
+                    state.traces[i] = trace.clone();
+                    ImportStatus::Updated
+                }
+                (None, _) => {
+                    state.traces.push(trace.clone());
+                    ImportStatus::Inserted
+                }
+            };
+
+            if status != ImportStatus::Skipped {
+                let row = state
+                    .sessions
+                    .entry(trace.session_id.clone())
+                    .or_insert_with(SessionRow::new);
+                if status == ImportStatus::Inserted {
+                    row.add_unsummarized(chars, tokens);
+                }
+            }
+            statuses.push(status);
+        }
+
+        Box::pin(async { Ok(statuses) })
+    }
+
+    fn query<'a>(
+        &'a self,
+        query: &'a SearchQuery,
+    ) -> StoreFuture<'a, Vec<Trace>> {
+        let state = self.lock();
+
+        let terms: Option<Vec<String>> = query
+            .text
+            .as_deref()
+            .map(|text| query_terms(text).flat_map(split_words).collect());
+
+        let mut matches: Vec<(usize, Trace)> = Vec::new();
+        for trace in &state.traces {
+            if !matches_filters(trace, query) {
+                continue;
+            }
+
+            let score = match &terms {
+                Some(terms) => match text_score(trace, terms) {
+                    0 => continue,
+                    score => score,
+                },
+                None => 0,
+            };
+            matches.push((score, trace.clone()));
+        }
+
+        matches.sort_by(|a, b| {
+            b.0.cmp(&a.0).then(b.1.created_at.cmp(&a.1.created_at))
+        });
+        matches.truncate(query.limit);
+
+        let traces = matches.into_iter().map(|(_, trace)| trace).collect();
+        Box::pin(async { Ok(traces) })
+    }
+
+    fn recent<'a>(
+        &'a self,
+        session_id: &'a str,
+        n: usize,
+    ) -> StoreFuture<'a, Vec<Trace>> {
+        let mut traces = self.lock().session_traces(session_id);
+        traces.drain(..traces.len().saturating_sub(n));
+        Box::pin(async { Ok(traces) })
+    }
+
+    fn session_traces<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Vec<Trace>> {
+        let traces = self.lock().session_traces(session_id);
+        Box::pin(async { Ok(traces) })
+    }
+
+    fn traces_after<'a>(
+        &'a self,
+        session_id: &'a str,
+        trace_id: Option<&'a str>,
+    ) -> StoreFuture<'a, Vec<Trace>> {
+        // Ties on the timestamp keep insertion order
+        let mut traces = self.lock().session_traces(session_id);
+        if let Some(i) =
+            trace_id.and_then(|id| traces.iter().position(|t| t.id == id))
+        {
+            traces.drain(..=i);
+        }
+        Box::pin(async { Ok(traces) })
+    }
+
+    fn export_traces<'a>(
+        &'a self,
+        filter: &'a ExportFilter,
+        cursor: Option<&'a Trace>,
+        limit: usize,
+    ) -> StoreFuture<'a, Vec<Trace>> {
+        let state = self.lock();
+        let key = |t: &Trace| (t.created_at, t.id.clone());
+
+        let mut traces: Vec<Trace> = state
+            .traces
+            .iter()
+            .filter(|t| {
+                matches_export_filter(t, filter)
+                    && cursor.is_none_or(|cursor| key(t) > key(cursor))
+            })
+            .cloned()
+            .collect();
+        traces.sort_by_key(key);
+        traces.truncate(limit);
+        Box::pin(async { Ok(traces) })
+    }
+
+    fn embedded_traces(&self) -> StoreFuture<'_, Vec<Trace>> {
+        let traces = self
+            .lock()
+            .traces
+            .iter()
+            .filter(|t| t.embedding.is_some())
+            .cloned()
+            .collect();
+        Box::pin(async { Ok(traces) })
+    }
+
+    fn unembedded_traces(&self) -> StoreFuture<'_, Vec<Trace>> {
+        let traces = self
+            .lock()
+            .traces
+            .iter()
+            .filter(|t| t.embedding.is_none())
+            .cloned()
+            .collect();
+        Box::pin(async { Ok(traces) })
+    }
+
+    fn set_embedding<'a>(
+        &'a self,
+        trace_id: &'a str,
+        embedding: &'a str,
+    ) -> StoreFuture<'a, ()> {
+        let mut state = self.lock();
+        if let Some(trace) = state.traces.iter_mut().find(|t| t.id == trace_id)
+        {
+            trace.embedding = Some(embedding.to_string());
+        }
+        Box::pin(async { Ok(()) })
+    }
+
+    fn list_sessions(&self) -> StoreFuture<'_, Vec<Session>> {
+        let state = self.lock();
+        let mut sessions: Vec<Session> =
+            state.sessions.keys().filter_map(|id| state.session(id)).collect();
+        sessions.sort_by(|a, b| {
+            b.updated_at.cmp(&a.updated_at).then(a.id.cmp(&b.id))
+        });
+        Box::pin(async { Ok(sessions) })
+    }
+
+    fn get_session<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Option<Session>> {
+        let session = self.lock().session(session_id);
+        Box::pin(async { Ok(session) })
+    }
+
+    fn delete_session<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, bool> {
+        let mut state = self.lock();
+        state.traces.retain(|t| t.session_id != session_id);
+        state.summaries.retain(|s| s.session_id != session_id);
+        let existed = state.sessions.remove(session_id).is_some();
+        Box::pin(async move { Ok(existed) })
+    }
+
+    fn rename_session<'a>(
+        &'a self,
+        old_id: &'a str,
+        new_id: &'a str,
+    ) -> StoreFuture<'a, ()> {
+        let mut state = self.lock();
+        let result = if state.sessions.contains_key(new_id) {
+            Err(Error::Other(format!("Session already exists: {}", new_id)))
+        } else if let Some(row) = state.sessions.remove(old_id) {
+            state.sessions.insert(
+                new_id.to_string(),
+                SessionRow { updated_at: Utc::now(), ..row },
+            );
+            for trace in state.traces.iter_mut() {
+                if trace.session_id == old_id {
+                    trace.session_id = new_id.to_string();
+                }
+            }
+            for summary in state.summaries.iter_mut() {
+                if summary.session_id == old_id {
+                    summary.session_id = new_id.to_string();
+                }
+            }
+            Ok(())
+        } else {
+            Err(Error::Other(format!("Session not found: {}", old_id)))
+        };
+        Box::pin(async { result })
+    }
+
+    fn add_summary<'a>(
+        &'a self,
+        summary: &'a SessionSummary,
+    ) -> StoreFuture<'a, ()> {
+        let mut state = self.lock();
+        let result = match state.sessions.get_mut(&summary.session_id) {
+            Some(row) => {
+                row.summary = Some(summary.content.clone());
+                row.summary_checkpoint = summary.last_trace_id.clone();
+                row.updated_at = Utc::now();
+                state.summaries.push(summary.clone());
+                Ok(())
+            }
+            None => Err(Error::Other(format!(
+                "Session not found: {}",
+                summary.session_id
+            ))),
+        };
+        Box::pin(async { result })
+    }
+
+    fn list_summaries<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Vec<SessionSummary>> {
+        let summaries = self.lock().session_summaries(session_id);
+        Box::pin(async { Ok(summaries) })
+    }
+
+    fn latest_summary<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Option<SessionSummary>> {
+        let summary = self.lock().session_summaries(session_id).pop();
+        Box::pin(async { Ok(summary) })
+    }
+
+    fn search_summaries<'a>(
+        &'a self,
+        text: &'a str,
+        limit: usize,
+    ) -> StoreFuture<'a, Vec<SessionSummary>> {
+        let state = self.lock();
+        let terms: Vec<String> =
+            query_terms(text).flat_map(split_words).collect();
+
+        let mut matches: Vec<(usize, SessionSummary)> = state
+            .summaries
+            .iter()
+            .map(|s| (fields_score(&[&s.content], &terms), s.clone()))
+            .filter(|(score, _)| *score > 0)
+            .collect();
+        matches.sort_by(|a, b| {
+            b.0.cmp(&a.0).then(b.1.created_at.cmp(&a.1.created_at))
+        });
+        matches.truncate(limit);
+
+        let summaries = matches.into_iter().map(|(_, s)| s).collect();
+        Box::pin(async { Ok(summaries) })
+    }
+}
+
+/// Split a query term into lowercased words the way the FTS5 tokenizer would
+fn split_words(term: &str) -> Vec<String> {
+    term.split('_')
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Count how many query terms appear in a trace's role, content or metadata
+fn text_score(trace: &Trace, terms: &[String]) -> usize {
+    let metadata = serde_json::to_string(&trace.metadata).unwrap_or_default();
+    fields_score(&[trace.role.as_str(), &trace.content, &metadata], terms)
+}
+
+/// Count how many query terms appear in any of `fields`
+fn fields_score(fields: &[&str], terms: &[String]) -> usize {
+    let words: Vec<String> = fields
+        .iter()
+        .flat_map(|field| query_terms(field).flat_map(split_words))
+        .collect();
+
+    terms
+        .iter()
+        .filter(|term| {
+            words.iter().any(|word| {
+                word == *term
+                    || (is_prefix_term(term)
+                        && word.starts_with(term.as_str()))
+            })
+        })
+        .count()
+}
+
+/// Check an export filter against a trace
+fn matches_export_filter(trace: &Trace, filter: &ExportFilter) -> bool {
+    if let SessionScope::Only(ids) = &filter.scope
+        && !ids.contains(&trace.session_id)
+    {
+        return false;
+    }
+
+    !(filter.since.is_some_and(|since| trace.created_at < since)
+        || filter.until.is_some_and(|until| trace.created_at > until))
+}
+
+/// Check every non-text filter of a query against a trace
+fn matches_filters(trace: &Trace, query: &SearchQuery) -> bool {
+    if !query.roles.is_empty()
+        && !query.roles.iter().any(|role| {
+            role.as_str().eq_ignore_ascii_case(trace.role.as_str())
+        })
+    {
+        return false;
+    }
+
+    if let SessionScope::Only(ids) = &query.scope
+        && !ids.contains(&trace.session_id)
+    {
+        return false;
+    }
+
+    if query.since.is_some_and(|since| trace.created_at < since)
+        || query.until.is_some_and(|until| trace.created_at > until)
+    {
+        return false;
+    }
+
+    if query.success_only && !trace.is_success() {
+        return false;
+    }
+
+    query.metadata.iter().all(|filter| matches_metadata(trace, filter))
+}
+
+/// Evaluate a metadata predicate with SQL-like null semantics
+fn matches_metadata(trace: &Trace, filter: &MetadataFilter) -> bool {
+    let actual = trace.metadata.get(&filter.key).filter(|v| !v.is_null());
+
+    let Some(actual) = actual else {
+        return filter.value.is_null() && filter.op == CompareOp::Eq;
+    };
+    if filter.value.is_null() {
+        return filter.op == CompareOp::Ne;
+    }
+
+    let ordering = compare_values(actual, &filter.value);
+    match filter.op {
+        CompareOp::Eq => ordering == Some(Ordering::Equal),
+        CompareOp::Ne => ordering != Some(Ordering::Equal),
+        CompareOp::Gt => ordering == Some(Ordering::Greater),
+        CompareOp::Gte => {
+            matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+        }
+        CompareOp::Lt => ordering == Some(Ordering::Less),
+        CompareOp::Lte => {
+            matches!(ordering, Some(Ordering::Less | Ordering::Equal))
+        }
+    }
+}
+
+/// Compare two JSON values of the same kind
+fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            a.as_f64()?.partial_cmp(&b.as_f64()?)
+        }
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
@@ -0,0 +1,918 @@
+//! SQLite storage backend with FTS5 search
+
+use super::{HistoryStore, StoreFuture};
+use crate::{
+    CompareOp, DuplicateStrategy, Error, ExportFilter, ImportStatus,
+    MetadataFilter, Result, SearchQuery, Session, SessionScope,
+    SessionSummary, Trace, build_fts_query, session::parse_sqlite_datetime,
+};
+use chrono::Utc;
+use serde_json::Value;
+use sqlx::{
+    QueryBuilder, Row,
+    sqlite::{Sqlite, SqliteConnection, SqlitePool, SqliteRow},
+};
+use std::{collections::HashMap, path::Path};
+
+/// Columns selected for every trace query
+const TRACE_COLUMNS: &str = "t.id, t.session_id, t.role, t.content, t.metadata, t.created_at, t.embedding";
+
+/// Columns selected for every summary query
+const SUMMARY_COLUMNS: &str = "s.id, s.session_id, s.content, s.model, s.first_trace_id, s.last_trace_id, s.created_at";
+
+/// SQLite-backed history store (the default)
+#[derive(Clone)]
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Open (or create) a SQLite database and run migrations
+    ///
+    /// # Arguments
+    /// * `path` - Path to SQLite database file (":memory:" for in-memory)
+    pub async fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path_str = path.as_ref().to_string_lossy().to_string();
+        let db_url = if path_str == ":memory:" {
+            "sqlite::memory:".to_string()
+        } else {
+            format!("sqlite://{}", path_str)
+        };
+
+        let pool = SqlitePool::connect(&db_url).await?;
+        Self::from_pool(pool).await
+    }
+
+    /// Use an existing pool, running migrations on it
+    pub async fn from_pool(pool: SqlitePool) -> Result<Self> {
+        // Run migrations
+        sqlx::migrate!("./migrations").run(&pool).await?;
+
+        Ok(Self { pool })
+    }
+
+    /// Get the underlying connection pool
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    async fn fetch_traces(
+        &self,
+        sql: &str,
+        binds: &[&str],
+    ) -> Result<Vec<Trace>> {
+        let mut query = sqlx::query(sql);
+        for bind in binds {
+            query = query.bind(*bind);
+        }
+
+        let rows = query.fetch_all(&self.pool).await?;
+
+        let mut traces = Vec::new();
+        for row in rows {
+            traces.push(row_to_trace(row)?);
+        }
+
+        Ok(traces)
+    }
+
+    async fn query_traces(&self, query: &SearchQuery) -> Result<Vec<Trace>> {
+        let fts_query = match (&query.text, query.raw) {
+            (None, _) => None,
+            (Some(text), true) => Some(text.clone()),
+            (Some(text), false) => match build_fts_query(text) {
+                Some(fts_query) => Some(fts_query),
+                // Nothing searchable left after tokenizing (e.g. only punctuation)
+                None => return Ok(Vec::new()),
+            },
+        };
+
+        let mut qb = QueryBuilder::<Sqlite>::new(format!(
+            "SELECT {} FROM traces t",
+            TRACE_COLUMNS
+        ));
+
+        if fts_query.is_some() {
+            qb.push(" JOIN traces_fts fts ON t.rowid = fts.rowid");
+        }
+        qb.push(" WHERE 1 = 1");
+
+        if let Some(fts_query) = &fts_query {
+            qb.push(" AND traces_fts MATCH ").push_bind(fts_query.clone());
+        }
+
+        // Older rows may hold roles in any case
+        if !query.roles.is_empty() {
+            qb.push(" AND LOWER(t.role) IN (");
+            let mut roles = qb.separated(", ");
+            for role in &query.roles {
+                roles.push_bind(role.as_str().to_lowercase());
+            }
+            roles.push_unseparated(")");
+        }
+
+        match &query.scope {
+            SessionScope::All | SessionScope::Current => {}
+            SessionScope::Only(ids) if ids.is_empty() => {
+                return Ok(Vec::new());
+            }
+            SessionScope::Only(ids) => {
+                qb.push(" AND t.session_id IN (");
+                let mut sessions = qb.separated(", ");
+                for id in ids {
+                    sessions.push_bind(id.clone());
+                }
+                sessions.push_unseparated(")");
+            }
+        }
+
+        // Compare as julian days so differing RFC 3339 precisions order correctly
+        if let Some(since) = query.since {
+            qb.push(" AND julianday(t.created_at) >= julianday(")
+                .push_bind(since.to_rfc3339())
+                .push(")");
+        }
+        if let Some(until) = query.until {
+            qb.push(" AND julianday(t.created_at) <= julianday(")
+                .push_bind(until.to_rfc3339())
+                .push(")");
+        }
+
+        for filter in &query.metadata {
+            push_metadata_filter(&mut qb, filter)?;
+        }
+
+        if query.success_only {
+            qb.push(
+                " AND COALESCE(json_type(t.metadata, '$.success'), '') != 'false'",
+            );
+        }
+
+        if fts_query.is_some() {
+            qb.push(" ORDER BY rank, t.created_at DESC");
+        } else {
+            qb.push(" ORDER BY t.created_at DESC");
+        }
+        qb.push(" LIMIT ").push_bind(query.limit as i64);
+
+        let rows = qb.build().fetch_all(&self.pool).await?;
+
+        let mut traces = Vec::new();
+        for row in rows {
+            traces.push(row_to_trace(row)?);
+        }
+
+        Ok(traces)
+    }
+
+    async fn import_batch(
+        &self,
+        traces: &[Trace],
+        usage: &[(i64, i64)],
+        duplicates: DuplicateStrategy,
+    ) -> Result<Vec<ImportStatus>> {
+        let mut tx = self.pool.begin().await?;
+        let mut statuses = Vec::with_capacity(traces.len());
+
+        for (trace, &(chars, tokens)) in traces.iter().zip(usage) {
+            let exists: bool = sqlx::query_scalar(
+                "SELECT EXISTS(SELECT 1 FROM traces WHERE id = ?)",
+            )
+            .bind(&trace.id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            let status = match (exists, duplicates) {
+                (true, DuplicateStrategy::Skip) => ImportStatus::Skipped,
+                (true, DuplicateStrategy::Fail) => {
+                    statuses.push(ImportStatus::Duplicate);
+                    break;
+                }
+                (true, DuplicateStrategy::Upsert) => ImportStatus::Updated,
+                (false, _) => ImportStatus::Inserted,
+            };
+
+            if status != ImportStatus::Skipped {
+                sqlx::query(
+                    "INSERT OR IGNORE INTO sessions (id, updated_at) VALUES (?, datetime('now'))",
+                )
+                .bind(&trace.session_id)
+                .execute(&mut *tx)
+                .await?;
+
+                sqlx::query(
+                    r#"
+                    INSERT INTO traces (id, session_id, role, content, metadata, created_at, embedding)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)
+                    ON CONFLICT (id) DO UPDATE SET
+                        session_id = excluded.session_id,
+                        role = excluded.role,
+                        content = excluded.content,
+                        metadata = excluded.metadata,
+                        created_at = excluded.created_at,
+                        embedding = excluded.embedding
+                    "#,
+                )
+                .bind(&trace.id)
+                .bind(&trace.session_id)
+                .bind(&trace.role)
+                .bind(&trace.content)
+                .bind(serde_json::to_string(&trace.metadata)?)
+                .bind(trace.created_at.to_rfc3339())
+                .bind(&trace.embedding)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            if status == ImportStatus::Inserted {
+                add_unsummarized(&mut tx, &trace.session_id, chars, tokens)
+                    .await?;
+            }
+
+            statuses.push(status);
+        }
+
+        tx.commit().await?;
+        Ok(statuses)
+    }
+
+    async fn export_page(
+        &self,
+        filter: &ExportFilter,
+        cursor: Option<&Trace>,
+        limit: usize,
+    ) -> Result<Vec<Trace>> {
+        let mut qb = QueryBuilder::<Sqlite>::new(format!(
+            "SELECT {} FROM traces t WHERE 1 = 1",
+            TRACE_COLUMNS
+        ));
+
+        match &filter.scope {
+            SessionScope::All | SessionScope::Current => {}
+            SessionScope::Only(ids) if ids.is_empty() => {
+                return Ok(Vec::new());
+            }
+            SessionScope::Only(ids) => {
+                qb.push(" AND t.session_id IN (");
+                let mut sessions = qb.separated(", ");
+                for id in ids {
+                    sessions.push_bind(id.clone());
+                }
+                sessions.push_unseparated(")");
+            }
+        }
+
+        if let Some(since) = filter.since {
+            qb.push(" AND julianday(t.created_at) >= julianday(")
+                .push_bind(since.to_rfc3339())
+                .push(")");
+        }
+        if let Some(until) = filter.until {
+            qb.push(" AND julianday(t.created_at) <= julianday(")
+                .push_bind(until.to_rfc3339())
+                .push(")");
+        }
+
+        // Keyset pagination on the same (julian day, id) key as the ordering
+        if let Some(cursor) = cursor {
+            let created_at = cursor.created_at.to_rfc3339();
+            qb.push(" AND (julianday(t.created_at) > julianday(")
+                .push_bind(created_at.clone())
+                .push(") OR (julianday(t.created_at) = julianday(")
+                .push_bind(created_at)
+                .push(") AND t.id > ")
+                .push_bind(cursor.id.clone())
+                .push("))");
+        }
+
+        qb.push(" ORDER BY julianday(t.created_at), t.id LIMIT ")
+            .push_bind(limit as i64);
+
+        let rows = qb.build().fetch_all(&self.pool).await?;
+
+        let mut traces = Vec::new();
+        for row in rows {
+            traces.push(row_to_trace(row)?);
+        }
+
+        Ok(traces)
+    }
+
+    async fn fetch_sessions(
+        &self,
+        session_id: Option<&str>,
+    ) -> Result<Vec<Session>> {
+        let sql = format!(
+            r#"
+            SELECT s.id, s.summary, s.summary_checkpoint, s.updated_at,
+                   s.unsummarized_chars, s.unsummarized_tokens, s.agent_state,
+                   (SELECT COUNT(*) FROM traces t WHERE t.session_id = s.id) AS trace_count
+            FROM sessions s
+            {}
+            ORDER BY s.updated_at DESC, s.id ASC
+            "#,
+            if session_id.is_some() { "WHERE s.id = ?" } else { "" }
+        );
+
+        let mut query = sqlx::query(&sql);
+        if let Some(session_id) = session_id {
+            query = query.bind(session_id);
+        }
+
+        let rows = query.fetch_all(&self.pool).await?;
+
+        let mut sessions = Vec::new();
+        for row in rows {
+            sessions.push(row_to_session(row)?);
+        }
+
+        Ok(sessions)
+    }
+
+    async fn delete_session_rows(&self, session_id: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        // Delete traces explicitly so this works even with foreign keys off
+        sqlx::query("DELETE FROM traces WHERE session_id = ?")
+            .bind(session_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM summaries WHERE session_id = ?")
+            .bind(session_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let result = sqlx::query("DELETE FROM sessions WHERE id = ?")
+            .bind(session_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn rename_session_rows(
+        &self,
+        old_id: &str,
+        new_id: &str,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO sessions (
+                id, summary, summary_checkpoint, updated_at,
+                unsummarized_chars, unsummarized_tokens, agent_state
+            )
+            SELECT ?, summary, summary_checkpoint, datetime('now'),
+                   unsummarized_chars, unsummarized_tokens, agent_state
+            FROM sessions WHERE id = ?
+            "#,
+        )
+        .bind(new_id)
+        .bind(old_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if inserted.rows_affected() == 0 {
+            return Err(Error::Other(format!(
+                "Session not found: {}",
+                old_id
+            )));
+        }
+
+        sqlx::query("UPDATE traces SET session_id = ? WHERE session_id = ?")
+            .bind(new_id)
+            .bind(old_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            "UPDATE summaries SET session_id = ? WHERE session_id = ?",
+        )
+        .bind(new_id)
+        .bind(old_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM sessions WHERE id = ?")
+            .bind(old_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+impl HistoryStore for SqliteStore {
+    fn ensure_session<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            sqlx::query(
+                "INSERT OR IGNORE INTO sessions (id, updated_at) VALUES (?, datetime('now'))",
+            )
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+
+            Ok(())
+        })
+    }
+
+    fn touch_session<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            sqlx::query(
+                r#"
+                INSERT INTO sessions (id, updated_at) VALUES (?, datetime('now'))
+                ON CONFLICT(id) DO UPDATE SET updated_at = excluded.updated_at
+                "#,
+            )
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+
+            Ok(())
+        })
+    }
+
+    fn add_unsummarized<'a>(
+        &'a self,
+        session_id: &'a str,
+        chars: i64,
+        tokens: i64,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let mut conn = self.pool.acquire().await?;
+            add_unsummarized(&mut conn, session_id, chars, tokens).await
+        })
+    }
+
+    fn set_agent_state<'a>(
+        &'a self,
+        session_id: &'a str,
+        state: &'a Value,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            sqlx::query("UPDATE sessions SET agent_state = ? WHERE id = ?")
+                .bind(serde_json::to_string(state)?)
+                .bind(session_id)
+                .execute(&self.pool)
+                .await?;
+
+            Ok(())
+        })
+    }
+
+    fn insert_trace<'a>(
+        &'a self,
+        trace: &'a Trace,
+        chars: i64,
+        tokens: i64,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let metadata_json = serde_json::to_string(&trace.metadata)?;
+            let created_at = trace.created_at.to_rfc3339();
+            let mut tx = self.pool.begin().await?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO traces (id, session_id, role, content, metadata, created_at, embedding)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&trace.id)
+            .bind(&trace.session_id)
+            .bind(&trace.role)
+            .bind(&trace.content)
+            .bind(&metadata_json)
+            .bind(&created_at)
+            .bind(&trace.embedding)
+            .execute(&mut *tx)
+            .await?;
+
+            add_unsummarized(&mut tx, &trace.session_id, chars, tokens)
+                .await?;
+            tx.commit().await?;
+            Ok(())
+        })
+    }
+
+    fn import_traces<'a>(
+        &'a self,
+        traces: &'a [Trace],
+        usage: &'a [(i64, i64)],
+        duplicates: DuplicateStrategy,
+    ) -> StoreFuture<'a, Vec<ImportStatus>> {
+        Box::pin(self.import_batch(traces, usage, duplicates))
+    }
+
+    fn query<'a>(
+        &'a self,
+        query: &'a SearchQuery,
+    ) -> StoreFuture<'a, Vec<Trace>> {
+        Box::pin(self.query_traces(query))
+    }
+
+    fn recent<'a>(
+        &'a self,
+        session_id: &'a str,
+        n: usize,
+    ) -> StoreFuture<'a, Vec<Trace>> {
+        Box::pin(async move {
+            let rows = sqlx::query(&format!(
+                r#"
+                SELECT {} FROM traces t
+                WHERE t.session_id = ?
+                ORDER BY t.created_at DESC
+                LIMIT ?
+                "#,
+                TRACE_COLUMNS
+            ))
+            .bind(session_id)
+            .bind(n as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+            let mut traces = Vec::new();
+            for row in rows {
+                traces.push(row_to_trace(row)?);
+            }
+
+            // Reverse to get chronological order
+            traces.reverse();
+            Ok(traces)
+        })
+    }
+
+    fn session_traces<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Vec<Trace>> {
+        Box::pin(async move {
+            let sql = format!(
+                "SELECT {} FROM traces t WHERE t.session_id = ? ORDER BY t.created_at ASC",
+                TRACE_COLUMNS
+            );
+            self.fetch_traces(&sql, &[session_id]).await
+        })
+    }
+
+    fn traces_after<'a>(
+        &'a self,
+        session_id: &'a str,
+        trace_id: Option<&'a str>,
+    ) -> StoreFuture<'a, Vec<Trace>> {
+        Box::pin(async move {
+            let rows = sqlx::query(&format!(
+                r#"
+                SELECT {} FROM traces t
+                LEFT JOIN traces c ON c.id = ?
+                WHERE t.session_id = ?
+                  AND (c.id IS NULL
+                       OR (julianday(t.created_at), t.rowid)
+                          > (julianday(c.created_at), c.rowid))
+                ORDER BY julianday(t.created_at), t.rowid
+                "#,
+                TRACE_COLUMNS
+            ))
+            .bind(trace_id)
+            .bind(session_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+            let mut traces = Vec::new();
+            for row in rows {
+                traces.push(row_to_trace(row)?);
+            }
+
+            Ok(traces)
+        })
+    }
+
+    fn export_traces<'a>(
+        &'a self,
+        filter: &'a ExportFilter,
+        cursor: Option<&'a Trace>,
+        limit: usize,
+    ) -> StoreFuture<'a, Vec<Trace>> {
+        Box::pin(self.export_page(filter, cursor, limit))
+    }
+
+    fn embedded_traces(&self) -> StoreFuture<'_, Vec<Trace>> {
+        Box::pin(async move {
+            let sql = format!(
+                "SELECT {} FROM traces t WHERE t.embedding IS NOT NULL",
+                TRACE_COLUMNS
+            );
+            self.fetch_traces(&sql, &[]).await
+        })
+    }
+
+    fn unembedded_traces(&self) -> StoreFuture<'_, Vec<Trace>> {
+        Box::pin(async move {
+            let sql = format!(
+                "SELECT {} FROM traces t WHERE t.embedding IS NULL",
+                TRACE_COLUMNS
+            );
+            self.fetch_traces(&sql, &[]).await
+        })
+    }
+
+    fn set_embedding<'a>(
+        &'a self,
+        trace_id: &'a str,
+        embedding: &'a str,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            sqlx::query("UPDATE traces SET embedding = ? WHERE id = ?")
+                .bind(embedding)
+                .bind(trace_id)
+                .execute(&self.pool)
+                .await?;
+
+            Ok(())
+        })
+    }
+
+    fn list_sessions(&self) -> StoreFuture<'_, Vec<Session>> {
+        Box::pin(self.fetch_sessions(None))
+    }
+
+    fn get_session<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Option<Session>> {
+        Box::pin(async move {
+            Ok(self.fetch_sessions(Some(session_id)).await?.pop())
+        })
+    }
+
+    fn delete_session<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, bool> {
+        Box::pin(self.delete_session_rows(session_id))
+    }
+
+    fn rename_session<'a>(
+        &'a self,
+        old_id: &'a str,
+        new_id: &'a str,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(self.rename_session_rows(old_id, new_id))
+    }
+
+    fn add_summary<'a>(
+        &'a self,
+        summary: &'a SessionSummary,
+    ) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let mut tx = self.pool.begin().await?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO summaries
+                    (id, session_id, content, model, first_trace_id, last_trace_id, created_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&summary.id)
+            .bind(&summary.session_id)
+            .bind(&summary.content)
+            .bind(&summary.model)
+            .bind(&summary.first_trace_id)
+            .bind(&summary.last_trace_id)
+            .bind(summary.created_at.to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+
+            // Mirror the newest summary in the sessions table
+            sqlx::query(
+                r#"
+                UPDATE sessions
+                SET summary = ?, summary_checkpoint = ?, updated_at = datetime('now')
+                WHERE id = ?
+                "#,
+            )
+            .bind(&summary.content)
+            .bind(&summary.last_trace_id)
+            .bind(&summary.session_id)
+            .execute(&mut *tx)
+            .await?;
+
+            tx.commit().await?;
+
+            Ok(())
+        })
+    }
+
+    fn list_summaries<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Vec<SessionSummary>> {
+        Box::pin(async move {
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM summaries s WHERE s.session_id = ? ORDER BY s.created_at ASC, s.rowid ASC",
+                SUMMARY_COLUMNS
+            ))
+            .bind(session_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+            rows.into_iter().map(row_to_summary).collect()
+        })
+    }
+
+    fn latest_summary<'a>(
+        &'a self,
+        session_id: &'a str,
+    ) -> StoreFuture<'a, Option<SessionSummary>> {
+        Box::pin(async move {
+            let row = sqlx::query(&format!(
+                r#"
+                SELECT {} FROM summaries s
+                WHERE s.session_id = ?
+                ORDER BY s.created_at DESC, s.rowid DESC
+                LIMIT 1
+                "#,
+                SUMMARY_COLUMNS
+            ))
+            .bind(session_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+            row.map(row_to_summary).transpose()
+        })
+    }
+
+    fn search_summaries<'a>(
+        &'a self,
+        text: &'a str,
+        limit: usize,
+    ) -> StoreFuture<'a, Vec<SessionSummary>> {
+        Box::pin(async move {
+            let Some(fts_query) = build_fts_query(text) else {
+                return Ok(Vec::new());
+            };
+
+            let rows = sqlx::query(&format!(
+                r#"
+                SELECT {} FROM summaries s
+                JOIN summaries_fts fts ON s.rowid = fts.rowid
+                WHERE summaries_fts MATCH ?
+                ORDER BY rank, s.created_at DESC
+                LIMIT ?
+                "#,
+                SUMMARY_COLUMNS
+            ))
+            .bind(fts_query)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+            rows.into_iter().map(row_to_summary).collect()
+        })
+    }
+}
+
+/// Add to a session's unsummarized usage, stopping at zero
+async fn add_unsummarized(
+    conn: &mut SqliteConnection,
+    session_id: &str,
+    chars: i64,
+    tokens: i64,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE sessions
+        SET unsummarized_chars = MAX(unsummarized_chars + ?, 0),
+            unsummarized_tokens = MAX(unsummarized_tokens + ?, 0)
+        WHERE id = ?
+        "#,
+    )
+    .bind(chars)
+    .bind(tokens)
+    .bind(session_id)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Append a `json_extract` predicate for a metadata filter
+fn push_metadata_filter(
+    qb: &mut QueryBuilder<'_, Sqlite>,
+    filter: &MetadataFilter,
+) -> Result<()> {
+    let path = format!("$.\"{}\"", filter.key);
+    qb.push(" AND json_extract(t.metadata, ").push_bind(path).push(")");
+
+    match &filter.value {
+        Value::Null => match filter.op {
+            CompareOp::Eq => {
+                qb.push(" IS NULL");
+            }
+            CompareOp::Ne => {
+                qb.push(" IS NOT NULL");
+            }
+            op => {
+                return Err(Error::Other(format!(
+                    "Cannot compare metadata '{}' to null with {}",
+                    filter.key,
+                    op.as_sql()
+                )));
+            }
+        },
+        value => {
+            qb.push(" ").push(filter.op.as_sql()).push(" ");
+            match value {
+                // json_extract returns booleans as 1/0
+                Value::Bool(b) => qb.push_bind(*b as i64),
+                Value::Number(n) => match n.as_i64() {
+                    Some(i) => qb.push_bind(i),
+                    None => qb.push_bind(n.as_f64().unwrap_or_default()),
+                },
+                Value::String(s) => qb.push_bind(s.clone()),
+                // Arrays and objects are extracted as minified JSON text
+                other => qb.push_bind(serde_json::to_string(other)?),
+            };
+        }
+    }
+
+    Ok(())
+}
+
+/// Convert a SQLx row to a Trace
+fn row_to_trace(row: SqliteRow) -> Result<Trace> {
+    let metadata_str: String = row.try_get("metadata")?;
+    let metadata: HashMap<String, Value> =
+        serde_json::from_str(&metadata_str)?;
+
+    let created_at_str: String = row.try_get("created_at")?;
+    let created_at = chrono::DateTime::parse_from_rfc3339(&created_at_str)
+        .map_err(|e| Error::Other(format!("Invalid datetime: {}", e)))?
+        .with_timezone(&Utc);
+
+    Ok(Trace {
+        id: row.try_get("id")?,
+        session_id: row.try_get("session_id")?,
+        role: row.try_get("role")?,
+        content: row.try_get("content")?,
+        metadata,
+        created_at,
+        embedding: row.try_get("embedding")?,
+    })
+}
+
+/// Convert a SQLx row to a SessionSummary
+fn row_to_summary(row: SqliteRow) -> Result<SessionSummary> {
+    let created_at_str: String = row.try_get("created_at")?;
+    let created_at = chrono::DateTime::parse_from_rfc3339(&created_at_str)
+        .map_err(|e| Error::Other(format!("Invalid datetime: {}", e)))?
+        .with_timezone(&Utc);
+
+    Ok(SessionSummary {
+        id: row.try_get("id")?,
+        session_id: row.try_get("session_id")?,
+        content: row.try_get("content")?,
+        model: row.try_get("model")?,
+        first_trace_id: row.try_get("first_trace_id")?,
+        last_trace_id: row.try_get("last_trace_id")?,
+        created_at,
+    })
+}
+
+/// Convert a SQLx row to a Session
+fn row_to_session(row: SqliteRow) -> Result<Session> {
+    let updated_at_str: String = row.try_get("updated_at")?;
+    let updated_at =
+        parse_sqlite_datetime(&updated_at_str).ok_or_else(|| {
+            Error::Other(format!("Invalid datetime: {}", updated_at_str))
+        })?;
+    let trace_count: i64 = row.try_get("trace_count")?;
+    let unsummarized_chars: i64 = row.try_get("unsummarized_chars")?;
+    let unsummarized_tokens: i64 = row.try_get("unsummarized_tokens")?;
+    let agent_state: Option<String> = row.try_get("agent_state")?;
+
+    Ok(Session {
+        id: row.try_get("id")?,
+        summary: row.try_get("summary")?,
+        summary_checkpoint: row.try_get("summary_checkpoint")?,
+        updated_at,
+        trace_count: trace_count as usize,
+        unsummarized_chars: unsummarized_chars as usize,
+        unsummarized_tokens: unsummarized_tokens as usize,
+        agent_state: agent_state
+            .map(|state| serde_json::from_str(&state))
+            .transpose()?,
+    })
+}
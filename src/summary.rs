@@ -0,0 +1,423 @@
+//! Prompt templates and assembly for incremental session summaries
+
+use crate::{Error, Result, Role, Trace};
+use rig::{
+    agent::Agent,
+    completion::{Chat, CompletionModel},
+};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Default prompt for summarizing a conversation from scratch
+const INITIAL_TEMPLATE: &str = "Please provide a concise summary of the following conversation:\n\n{transcript}";
+
+/// Default prompt for folding new turns into an existing summary
+const UPDATE_TEMPLATE: &str = "Here is a summary of a conversation so far:\n\n{previous_summary}\n\nUpdate it with the following new messages, keeping it concise:\n\n{transcript}";
+
+/// Default prompt for summarizing one part of a long conversation
+const CHUNK_TEMPLATE: &str = "Please provide a concise summary of part {part} of {total} of a conversation:\n\n{transcript}";
+
+/// Default prompt for merging the summaries of consecutive parts
+const COMBINE_TEMPLATE: &str = "Combine these summaries of consecutive parts of one conversation into a single concise summary:\n\n{summaries}";
+
+/// Default format of one transcript line
+const LINE_TEMPLATE: &str = "{role}: {content}";
+
+/// Settings for [`AgentHistory::summarize_session`](crate::AgentHistory::summarize_session)
+///
+/// Summaries are incremental: each run feeds the previous summary plus the
+/// traces logged since it to the model. Transcripts longer than
+/// `chunk_chars` are split; the first summary of a large session is built
+/// map-reduce style (one partial summary per chunk, then a combined one),
+/// while updates fold the chunks into the running summary one at a time.
+///
+/// Prompts are templates with `{placeholder}`s. Every prompt can use
+/// `{session_id}`; the others are:
+///
+/// | Template  | Placeholders                          |
+/// |-----------|---------------------------------------|
+/// | `initial` | `{transcript}`                        |
+/// | `update`  | `{previous_summary}`, `{transcript}`  |
+/// | `chunk`   | `{part}`, `{total}`, `{transcript}`   |
+/// | `combine` | `{summaries}`                         |
+///
+/// Transcript lines use `{role}`, `{content}` and `{timestamp}`. Unknown
+/// placeholders are left as they are.
+///
+/// # Example
+/// ```rust
+/// # use agentsmith::{AgentHistory, MemoryStore, SummaryConfig, SummaryFormat};
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let config = SummaryConfig::default()
+///     .with_initial_template(
+///         "Summarize session {session_id} for the on-call engineer:\n\n{transcript}",
+///     )
+///     .with_role_format("tool", "  (tool output) {content}")
+///     .with_max_words(150)
+///     .with_format(SummaryFormat::structured());
+///
+/// let history = AgentHistory::with_store(MemoryStore::new(), None)
+///     .await?
+///     .with_summary_config(config);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SummaryConfig {
+    /// Maximum transcript length sent in one prompt (default: 12000)
+    pub chunk_chars: usize,
+
+    /// Model name recorded with each summary (default: none)
+    pub model_name: Option<String>,
+
+    /// Prompt for the first summary of a session
+    pub initial_template: String,
+
+    /// Prompt for updating an existing summary with new turns
+    pub update_template: String,
+
+    /// Prompt for one part of a long first-time transcript
+    pub chunk_template: String,
+
+    /// Prompt for merging the summaries of those parts
+    pub combine_template: String,
+
+    /// Transcript line format for roles without their own
+    /// (default: `{role}: {content}`)
+    pub line_template: String,
+
+    /// Transcript line formats by role
+    pub role_formats: HashMap<String, String>,
+
+    /// Ask the model to stay under this many words (default: no limit)
+    pub max_words: Option<usize>,
+
+    /// Shape of the summary text (default: free text)
+    pub format: SummaryFormat,
+}
+
+/// Shape of generated summaries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SummaryFormat {
+    /// Free-form text
+    #[default]
+    Text,
+
+    /// A JSON object whose keys each hold an array of strings
+    ///
+    /// The model's reply is validated and stored as compact JSON; keys it
+    /// left out are filled with empty arrays.
+    Json(Vec<String>),
+}
+
+impl SummaryFormat {
+    /// JSON with `decisions`, `open_questions` and `facts`
+    pub fn structured() -> Self {
+        SummaryFormat::Json(vec![
+            "decisions".to_string(),
+            "open_questions".to_string(),
+            "facts".to_string(),
+        ])
+    }
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        Self {
+            chunk_chars: 12_000,
+            model_name: None,
+            initial_template: INITIAL_TEMPLATE.to_string(),
+            update_template: UPDATE_TEMPLATE.to_string(),
+            chunk_template: CHUNK_TEMPLATE.to_string(),
+            combine_template: COMBINE_TEMPLATE.to_string(),
+            line_template: LINE_TEMPLATE.to_string(),
+            role_formats: HashMap::new(),
+            max_words: None,
+            format: SummaryFormat::Text,
+        }
+    }
+}
+
+impl SummaryConfig {
+    /// Set the maximum transcript length sent in one prompt
+    pub fn with_chunk_chars(mut self, chars: usize) -> Self {
+        self.chunk_chars = chars.max(1);
+        self
+    }
+
+    /// Set the model name recorded with each summary
+    pub fn with_model_name(mut self, name: impl Into<String>) -> Self {
+        self.model_name = Some(name.into());
+        self
+    }
+
+    /// Set the prompt for the first summary of a session
+    pub fn with_initial_template(
+        mut self,
+        template: impl Into<String>,
+    ) -> Self {
+        self.initial_template = template.into();
+        self
+    }
+
+    /// Set the prompt for updating an existing summary
+    pub fn with_update_template(
+        mut self,
+        template: impl Into<String>,
+    ) -> Self {
+        self.update_template = template.into();
+        self
+    }
+
+    /// Set the prompt for one part of a long transcript
+    pub fn with_chunk_template(mut self, template: impl Into<String>) -> Self {
+        self.chunk_template = template.into();
+        self
+    }
+
+    /// Set the prompt for merging the summaries of the parts
+    pub fn with_combine_template(
+        mut self,
+        template: impl Into<String>,
+    ) -> Self {
+        self.combine_template = template.into();
+        self
+    }
+
+    /// Set the transcript line format for roles without their own
+    pub fn with_line_template(mut self, template: impl Into<String>) -> Self {
+        self.line_template = template.into();
+        self
+    }
+
+    /// Set the transcript line format for one role
+    pub fn with_role_format(
+        mut self,
+        role: impl Into<Role>,
+        template: impl Into<String>,
+    ) -> Self {
+        let role = role.into().to_string();
+        self.role_formats.insert(role, template.into());
+        self
+    }
+
+    /// Ask the model to keep summaries under `words` words
+    pub fn with_max_words(mut self, words: usize) -> Self {
+        self.max_words = Some(words);
+        self
+    }
+
+    /// Set the shape of the summary text
+    pub fn with_format(mut self, format: SummaryFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Render traces as transcript lines, split into chunks of at most
+    /// `chunk_chars` characters
+    ///
+    /// A single line longer than `chunk_chars` gets a chunk of its own.
+    pub(crate) fn transcript_chunks(&self, traces: &[Trace]) -> Vec<String> {
+        let mut chunks = Vec::new();
+        let mut current = String::new();
+
+        for trace in traces {
+            let line = self.format_line(trace);
+            if !current.is_empty()
+                && current.chars().count() + line.chars().count()
+                    > self.chunk_chars
+            {
+                chunks.push(std::mem::take(&mut current));
+            }
+            current.push_str(&line);
+        }
+
+        if !current.is_empty() || chunks.is_empty() {
+            chunks.push(current);
+        }
+
+        chunks
+    }
+
+    /// Prompt for summarizing a conversation from scratch
+    pub(crate) fn initial_prompt(
+        &self,
+        session_id: &str,
+        transcript: &str,
+    ) -> String {
+        self.finish_prompt(render(
+            &self.initial_template,
+            &[("session_id", session_id), ("transcript", transcript)],
+        ))
+    }
+
+    /// Prompt for folding new turns into an existing summary
+    pub(crate) fn update_prompt(
+        &self,
+        session_id: &str,
+        previous: &str,
+        transcript: &str,
+    ) -> String {
+        self.finish_prompt(render(
+            &self.update_template,
+            &[
+                ("session_id", session_id),
+                ("previous_summary", previous),
+                ("transcript", transcript),
+            ],
+        ))
+    }
+
+    /// Prompt for summarizing one part of a long conversation
+    ///
+    /// Partial summaries are always free text; only the combined summary
+    /// follows [`SummaryConfig::format`].
+    pub(crate) fn chunk_prompt(
+        &self,
+        session_id: &str,
+        part: usize,
+        total: usize,
+        transcript: &str,
+    ) -> String {
+        render(
+            &self.chunk_template,
+            &[
+                ("session_id", session_id),
+                ("part", &part.to_string()),
+                ("total", &total.to_string()),
+                ("transcript", transcript),
+            ],
+        )
+    }
+
+    /// Prompt for merging the summaries of consecutive parts
+    pub(crate) fn combine_prompt(
+        &self,
+        session_id: &str,
+        partials: &[String],
+    ) -> String {
+        let mut parts = String::new();
+        for (i, partial) in partials.iter().enumerate() {
+            parts.push_str(&format!("Part {}:\n{}\n\n", i + 1, partial));
+        }
+
+        self.finish_prompt(render(
+            &self.combine_template,
+            &[("session_id", session_id), ("summaries", parts.trim_end())],
+        ))
+    }
+
+    /// Check and normalize a summary according to [`SummaryConfig::format`]
+    pub(crate) fn parse_reply(&self, reply: String) -> Result<String> {
+        let SummaryFormat::Json(keys) = &self.format else {
+            return Ok(reply);
+        };
+
+        // Models like to wrap JSON in prose or code fences
+        let json = match (reply.find('{'), reply.rfind('}')) {
+            (Some(start), Some(end)) if start < end => &reply[start..=end],
+            _ => reply.as_str(),
+        };
+        let Ok(Value::Object(mut object)) = serde_json::from_str(json) else {
+            return Err(Error::Other(format!(
+                "Summary is not a JSON object: {}",
+                reply
+            )));
+        };
+
+        for key in keys {
+            object.entry(key.clone()).or_insert_with(|| Value::Array(vec![]));
+        }
+
+        Ok(serde_json::to_string(&object)?)
+    }
+
+    /// Render one trace as a transcript line
+    fn format_line(&self, trace: &Trace) -> String {
+        let template = self
+            .role_formats
+            .get(trace.role.as_str())
+            .unwrap_or(&self.line_template);
+        let timestamp = trace.created_at.format("%Y-%m-%d %H:%M").to_string();
+
+        let mut line = render(
+            template,
+            &[
+                ("role", trace.role.as_str()),
+                ("content", &trace.content),
+                ("timestamp", &timestamp),
+            ],
+        );
+        line.push('\n');
+        line
+    }
+
+    /// Append the length hint and output format instructions
+    fn finish_prompt(&self, mut prompt: String) -> String {
+        if self.max_words.is_some() || self.format != SummaryFormat::Text {
+            prompt.truncate(prompt.trim_end().len());
+        }
+
+        if let Some(words) = self.max_words {
+            prompt.push_str(&format!(
+                "\n\nKeep the summary under {} words.",
+                words
+            ));
+        }
+
+        if let SummaryFormat::Json(keys) = &self.format {
+            let keys: Vec<String> =
+                keys.iter().map(|key| format!("\"{}\"", key)).collect();
+            prompt.push_str(&format!(
+                "\n\nRespond with only a JSON object with the keys {}, \
+                 each an array of strings.",
+                keys.join(", ")
+            ));
+        }
+
+        prompt
+    }
+}
+
+/// Substitute `{name}` placeholders in a single pass, so values that
+/// contain braces are inserted verbatim
+fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            values
+                .iter()
+                .find(|(name, _)| *name == &after[..end])
+                .map(|(_, value)| (end, *value))
+        });
+
+        match value {
+            Some((end, value)) => {
+                out.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+/// Send a one-off prompt to the summarizer
+pub(crate) async fn ask<M: CompletionModel>(
+    summarizer: &Agent<M>,
+    prompt: &str,
+) -> Result<String> {
+    summarizer
+        .chat(prompt, vec![])
+        .await
+        .map_err(|e| Error::Rig(e.to_string()))
+}
@@ -1,5 +1,6 @@
 //! Trace data structures for storing agent interactions
 
+use crate::Role;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -14,8 +15,8 @@ pub struct Trace {
     /// Session this trace belongs to
     pub session_id: String,
 
-    /// Role (user, assistant, system)
-    pub role: String,
+    /// Who the message came from
+    pub role: Role,
 
     /// Message content
     pub content: String,
@@ -27,18 +28,22 @@ pub struct Trace {
     /// When this trace was created
     pub created_at: DateTime<Utc>,
 
-    /// Optional embedding for semantic search (future use)
+    /// Optional embedding for semantic search, as a JSON array of floats
     #[serde(skip_serializing_if = "Option::is_none")]
     pub embedding: Option<String>,
 }
 
 impl Trace {
     /// Create a new trace
-    pub fn new(session_id: String, role: String, content: String) -> Self {
+    pub fn new(
+        session_id: String,
+        role: impl Into<Role>,
+        content: String,
+    ) -> Self {
         Self {
             id: uuid::Uuid::new_v4().to_string(),
             session_id,
-            role,
+            role: role.into(),
             content,
             metadata: HashMap::new(),
             created_at: Utc::now(),
@@ -62,6 +67,11 @@ impl Trace {
         self.metadata.get(key)
     }
 
+    /// Decode the stored embedding vector, if present and well-formed
+    pub fn embedding_vector(&self) -> Option<Vec<f64>> {
+        self.embedding.as_deref().and_then(|e| serde_json::from_str(e).ok())
+    }
+
     /// Check if this trace was successful (based on metadata)
     pub fn is_success(&self) -> bool {
         self.metadata.get("success").and_then(|v| v.as_bool()).unwrap_or(true)
@@ -0,0 +1,361 @@
+//! Markdown and HTML rendering of session transcripts
+
+use crate::{Role, SessionSummary, Trace};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Output format of a rendered transcript
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    /// Markdown, ready to paste into an issue or document
+    #[default]
+    Markdown,
+
+    /// A standalone HTML page with inline styles
+    Html,
+}
+
+/// Which metadata keys are shown next to each message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Badges {
+    /// No metadata
+    None,
+
+    /// These keys, in this order, when present
+    Keys(Vec<String>),
+
+    /// Every key, sorted
+    All,
+}
+
+/// Settings for [`AgentHistory::render_session`](crate::AgentHistory::render_session)
+///
+/// # Example
+/// ```rust
+/// use agentsmith::{TranscriptFormat, TranscriptOptions};
+///
+/// let options = TranscriptOptions::default()
+///     .with_format(TranscriptFormat::Html)
+///     .with_all_badges()
+///     .with_redacted("user_email")
+///     .with_collapsed_system(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct TranscriptOptions {
+    /// Markdown or HTML (default: Markdown)
+    pub format: TranscriptFormat,
+
+    /// Start with the session's latest summary (default: true)
+    pub summary: bool,
+
+    /// Show when each message was logged (default: true)
+    pub timestamps: bool,
+
+    /// Metadata shown as badges (default: `duration_ms` and `success`)
+    pub badges: Badges,
+
+    /// Metadata keys whose values are shown as `[redacted]`
+    pub redacted: HashSet<String>,
+
+    /// Fold system messages into a collapsed block (default: false)
+    pub collapse_system: bool,
+}
+
+impl Default for TranscriptOptions {
+    fn default() -> Self {
+        Self {
+            format: TranscriptFormat::Markdown,
+            summary: true,
+            timestamps: true,
+            badges: Badges::Keys(vec![
+                "duration_ms".to_string(),
+                "success".to_string(),
+            ]),
+            redacted: HashSet::new(),
+            collapse_system: false,
+        }
+    }
+}
+
+impl TranscriptOptions {
+    /// Set the output format
+    pub fn with_format(mut self, format: TranscriptFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set whether the latest summary is shown at the top
+    pub fn with_summary(mut self, summary: bool) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    /// Set whether message timestamps are shown
+    pub fn with_timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Show these metadata keys as badges, in order
+    pub fn with_badges<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.badges = Badges::Keys(keys.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Show every metadata key as a badge
+    pub fn with_all_badges(mut self) -> Self {
+        self.badges = Badges::All;
+        self
+    }
+
+    /// Leave metadata out entirely
+    pub fn without_badges(mut self) -> Self {
+        self.badges = Badges::None;
+        self
+    }
+
+    /// Show `[redacted]` instead of the value of a metadata key
+    pub fn with_redacted(mut self, key: impl Into<String>) -> Self {
+        self.redacted.insert(key.into());
+        self
+    }
+
+    /// Set whether system messages are collapsed
+    pub fn with_collapsed_system(mut self, collapse: bool) -> Self {
+        self.collapse_system = collapse;
+        self
+    }
+
+    /// Render a session's traces, oldest first
+    pub(crate) fn render(
+        &self,
+        session_id: &str,
+        traces: &[Trace],
+        summary: Option<&SessionSummary>,
+    ) -> String {
+        let summary = summary.filter(|_| self.summary);
+        match self.format {
+            TranscriptFormat::Markdown => {
+                self.markdown(session_id, traces, summary)
+            }
+            TranscriptFormat::Html => self.html(session_id, traces, summary),
+        }
+    }
+
+    fn markdown(
+        &self,
+        session_id: &str,
+        traces: &[Trace],
+        summary: Option<&SessionSummary>,
+    ) -> String {
+        let mut out = format!("# Session {}\n\n", code(session_id));
+
+        if let Some(summary) = summary {
+            out.push_str(&format!(
+                "> **Summary** ({})\n>\n",
+                summary.created_at.format("%Y-%m-%d %H:%M UTC")
+            ));
+            for line in summary.content.lines() {
+                if line.is_empty() {
+                    out.push_str(">\n");
+                } else {
+                    out.push_str(&format!("> {}\n", line));
+                }
+            }
+            out.push('\n');
+        }
+
+        for trace in traces {
+            let mut heading = role_title(trace.role.as_str());
+            if self.timestamps {
+                heading.push_str(&format!(" · {}", timestamp(trace)));
+            }
+            let badges: Vec<String> = self
+                .badges(trace)
+                .into_iter()
+                .map(|(key, value)| code(&format!("{}: {}", key, value)))
+                .collect();
+
+            let collapsed = self.collapse_system && trace.role == Role::System;
+
+            if collapsed {
+                out.push_str(&format!(
+                    "<details>\n<summary>{}</summary>\n\n",
+                    escape(&heading)
+                ));
+            } else {
+                out.push_str(&format!("### {}\n\n", heading));
+            }
+            if !badges.is_empty() {
+                out.push_str(&format!("{}\n\n", badges.join(" ")));
+            }
+            if collapsed {
+                // Raw HTML here would end the block or hide the content
+                out.push_str(&escape(trace.content.trim_end()));
+                out.push_str("\n\n</details>\n\n");
+            } else {
+                out.push_str(trace.content.trim_end());
+                out.push_str("\n\n");
+            }
+        }
+
+        out.truncate(out.trim_end().len());
+        out.push('\n');
+        out
+    }
+
+    fn html(
+        &self,
+        session_id: &str,
+        traces: &[Trace],
+        summary: Option<&SessionSummary>,
+    ) -> String {
+        let title = format!("Session {}", escape(session_id));
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n\
+             <meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            title, STYLE, title
+        );
+
+        if let Some(summary) = summary {
+            out.push_str(&format!(
+                "<section class=\"summary\">\n<h2>Summary</h2>\n\
+                 <time>{}</time>\n<div class=\"content\">{}</div>\n</section>\n",
+                summary.created_at.format("%Y-%m-%d %H:%M UTC"),
+                escape(&summary.content)
+            ));
+        }
+
+        for trace in traces {
+            let role = escape(trace.role.as_str());
+            let mut header = format!(
+                "<strong>{}</strong>",
+                escape(&role_title(trace.role.as_str()))
+            );
+            if self.timestamps {
+                header
+                    .push_str(&format!(" <time>{}</time>", timestamp(trace)));
+            }
+            for (key, value) in self.badges(trace) {
+                header.push_str(&format!(
+                    " <span class=\"badge\">{}: {}</span>",
+                    escape(&key),
+                    escape(&value)
+                ));
+            }
+            let content = format!(
+                "<div class=\"content\">{}</div>",
+                escape(&trace.content)
+            );
+
+            if self.collapse_system && trace.role == Role::System {
+                out.push_str(&format!(
+                    "<details class=\"message {}\">\n<summary>{}</summary>\n\
+                     {}\n</details>\n",
+                    role, header, content
+                ));
+            } else {
+                out.push_str(&format!(
+                    "<article class=\"message {}\">\n<header>{}</header>\n\
+                     {}\n</article>\n",
+                    role, header, content
+                ));
+            }
+        }
+
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// Metadata badges of one trace as `(key, value)` pairs
+    fn badges(&self, trace: &Trace) -> Vec<(String, String)> {
+        let keys: Vec<&String> = match &self.badges {
+            Badges::None => return Vec::new(),
+            Badges::Keys(keys) => keys.iter().collect(),
+            Badges::All => {
+                let mut keys: Vec<&String> = trace.metadata.keys().collect();
+                keys.sort();
+                keys
+            }
+        };
+
+        keys.into_iter()
+            .filter_map(|key| {
+                let value = trace.metadata.get(key)?;
+                let value = if self.redacted.contains(key) {
+                    "[redacted]".to_string()
+                } else {
+                    match value {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    }
+                };
+                Some((key.clone(), value))
+            })
+            .collect()
+    }
+}
+
+/// Inline styles of HTML transcripts
+const STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:50rem;\
+margin:2rem auto;padding:0 1rem;color:#222}\
+.summary{background:#f4f6f8;border-left:4px solid #8a9ba8;padding:.5rem 1rem}\
+.message{border-bottom:1px solid #e1e4e8;padding:.75rem 0}\
+.message header,.message summary{margin-bottom:.5rem}\
+time{color:#6a737d;font-size:.85em;margin-left:.5rem}\
+.badge{background:#eef1f4;border-radius:4px;font-family:monospace;\
+font-size:.8em;margin-left:.5rem;padding:.1rem .4rem}\
+.content{white-space:pre-wrap}\
+.user strong{color:#0366d6}.assistant strong{color:#28a745}\
+.error strong{color:#cb2431}.system{color:#586069}";
+
+/// "assistant" → "Assistant"
+fn role_title(role: &str) -> String {
+    let mut chars = role.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn timestamp(trace: &Trace) -> String {
+    trace.created_at.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+/// Markdown inline code span, fenced with more backticks than the text has
+/// in a row
+fn code(text: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in text.chars() {
+        run = if c == '`' { run + 1 } else { 0 };
+        longest = longest.max(run);
+    }
+    let fence = "`".repeat(longest + 1);
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{} {} {}", fence, text, fence)
+    } else {
+        format!("{}{}{}", fence, text, fence)
+    }
+}
+
+/// Escape text for HTML element content and attribute values
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -1,9 +1,142 @@
 //! Integration tests for agentsmith
 
-use agentsmith::{AgentHistory, Trace};
-use rig::completion::Message;
+use agentsmith::{
+    AgentHistory, CharHeuristic, ChunkStream, CompareOp, ContextBuilder,
+    Dedupe, DuplicateStrategy, ExportFilter, HashEmbedder, HybridConfig,
+    ImportOptions, MemoryStore, RecallOptions, Retriever, RetryPolicy, Role,
+    SearchQuery, SessionSummary, SmartAgent, SqliteStore, SummaryConfig,
+    SummaryFormat, SummaryTrigger, TokenCounter, Trace, TranscriptFormat,
+    TranscriptOptions, cosine_similarity,
+};
+use futures::{StreamExt, stream};
+use rig::{
+    agent::{Agent, AgentBuilder},
+    completion::{
+        CompletionError, CompletionModel, CompletionRequest,
+        CompletionResponse, Message, ModelChoice,
+    },
+};
 use serde_json::json;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Mock completion model that replies "echo: <prompt>" and records chat histories
+#[derive(Clone, Default)]
+struct MockModel {
+    requests: Arc<Mutex<Vec<Vec<Message>>>>,
+}
+
+impl MockModel {
+    fn agent(&self) -> Agent<MockModel> {
+        AgentBuilder::new(self.clone()).build()
+    }
+
+    fn last_history(&self) -> Vec<Message> {
+        self.requests.lock().unwrap().last().unwrap().clone()
+    }
+
+    fn request_count(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+}
+
+impl CompletionModel for MockModel {
+    type Response = ();
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<CompletionResponse<()>, CompletionError> {
+        self.requests.lock().unwrap().push(request.chat_history.clone());
+
+        Ok(CompletionResponse {
+            choice: ModelChoice::Message(format!("echo: {}", request.prompt)),
+            raw_response: (),
+        })
+    }
+}
+
+/// Mock completion model whose provider always fails
+#[derive(Clone, Default)]
+struct FailingModel;
+
+impl CompletionModel for FailingModel {
+    type Response = ();
+
+    async fn completion(
+        &self,
+        _request: CompletionRequest,
+    ) -> Result<CompletionResponse<()>, CompletionError> {
+        Err(CompletionError::ProviderError("service unavailable".to_string()))
+    }
+}
+
+/// Mock completion model that fails a set number of times, then recovers
+#[derive(Clone, Default)]
+struct FlakyModel {
+    failures: Arc<Mutex<usize>>,
+    calls: Arc<Mutex<usize>>,
+}
+
+impl FlakyModel {
+    fn new(failures: usize) -> Self {
+        Self { failures: Arc::new(Mutex::new(failures)), ..Self::default() }
+    }
+
+    fn agent(&self) -> Agent<FlakyModel> {
+        AgentBuilder::new(self.clone()).build()
+    }
+
+    fn calls(&self) -> usize {
+        *self.calls.lock().unwrap()
+    }
+}
+
+impl CompletionModel for FlakyModel {
+    type Response = ();
+
+    async fn completion(
+        &self,
+        _request: CompletionRequest,
+    ) -> Result<CompletionResponse<()>, CompletionError> {
+        *self.calls.lock().unwrap() += 1;
+        let mut failures = self.failures.lock().unwrap();
+        if *failures > 0 {
+            *failures -= 1;
+            return Err(CompletionError::ProviderError(
+                "overloaded".to_string(),
+            ));
+        }
+        Ok(CompletionResponse {
+            choice: ModelChoice::Message("recovered".to_string()),
+            raw_response: (),
+        })
+    }
+}
+
+/// Mock completion model that always gives the same reply
+#[derive(Clone)]
+struct FixedModel(String);
+
+impl FixedModel {
+    fn agent(reply: &str) -> Agent<FixedModel> {
+        AgentBuilder::new(FixedModel(reply.to_string())).build()
+    }
+}
+
+impl CompletionModel for FixedModel {
+    type Response = ();
+
+    async fn completion(
+        &self,
+        _request: CompletionRequest,
+    ) -> Result<CompletionResponse<()>, CompletionError> {
+        Ok(CompletionResponse {
+            choice: ModelChoice::Message(self.0.clone()),
+            raw_response: (),
+        })
+    }
+}
 
 #[tokio::test]
 async fn test_create_history_in_memory() {
@@ -212,14 +345,466 @@ async fn test_import_jsonl() {
     let path = temp_file.path().to_string_lossy().to_string();
 
     // Import
-    let count = history.import_jsonl(&path).await.unwrap();
-    assert_eq!(count, 2);
+    let report = history.import_jsonl(&path).await.unwrap();
+    assert_eq!(report.inserted, 2);
+    assert!(report.errors.is_empty());
 
     // Verify imported data
     let recent = history.recent(10).await.unwrap();
     assert_eq!(recent.len(), 2);
     assert_eq!(recent[0].content, "First message");
     assert_eq!(recent[1].content, "Second message");
+
+    // Imported traces count toward the next summary, skipped ones don't
+    let report = history.import_jsonl(&path).await.unwrap();
+    assert_eq!(report.skipped, 2);
+    let session = history.get_session("test").await.unwrap().unwrap();
+    assert_eq!(session.unsummarized_chars, 27);
+    assert_eq!(session.unsummarized_tokens, 8);
+}
+
+#[tokio::test]
+async fn test_import_jsonl_strategies() {
+    let history = AgentHistory::new(":memory:", Some("main")).await.unwrap();
+    let first = Trace::new(
+        "imported".to_string(),
+        "user".to_string(),
+        "original question".to_string(),
+    );
+    let second = Trace::new(
+        "imported".to_string(),
+        "assistant".to_string(),
+        "original answer".to_string(),
+    );
+    let jsonl = format!(
+        "{}\nnot json\n\n{}\n",
+        serde_json::to_string(&first).unwrap(),
+        serde_json::to_string(&second).unwrap()
+    );
+
+    // Malformed lines are reported and the rest still lands, with its
+    // session created on the fly
+    let options = ImportOptions::default().with_batch_size(1);
+    let report = history.read_jsonl(jsonl.as_bytes(), &options).await.unwrap();
+    assert_eq!(report.lines, 3);
+    assert_eq!(report.inserted, 2);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].line, 2);
+    let session = history.get_session("imported").await.unwrap().unwrap();
+    assert_eq!(session.trace_count, 2);
+
+    // Importing again is a no-op by default
+    let report = history.read_jsonl(jsonl.as_bytes(), &options).await.unwrap();
+    assert_eq!(report.inserted, 0);
+    assert_eq!(report.skipped, 2);
+
+    // Upserts replace the stored traces, search index included
+    let edited = Trace { content: "edited answer".to_string(), ..second };
+    let jsonl = serde_json::to_string(&edited).unwrap();
+    let upsert = options.clone().with_duplicates(DuplicateStrategy::Upsert);
+    let report = history.read_jsonl(jsonl.as_bytes(), &upsert).await.unwrap();
+    assert_eq!(report.updated, 1);
+    assert_eq!(report.imported(), 1);
+    let found = history.search("edited", 10, false).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, edited.id);
+    let found = history.search("original", 10, false).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, first.id);
+
+    // Failing on duplicates names the line; earlier lines are kept
+    let fresh = Trace::new(
+        "imported".to_string(),
+        "user".to_string(),
+        "new question".to_string(),
+    );
+    let jsonl = format!(
+        "{}\n{}\n",
+        serde_json::to_string(&fresh).unwrap(),
+        serde_json::to_string(&first).unwrap()
+    );
+    let fail =
+        ImportOptions::default().with_duplicates(DuplicateStrategy::Fail);
+    let error = history
+        .read_jsonl(jsonl.as_bytes(), &fail)
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("Line 2"), "{}", error);
+    let session = history.get_session("imported").await.unwrap().unwrap();
+    assert_eq!(session.trace_count, 3);
+}
+
+#[tokio::test]
+async fn test_roles() {
+    assert_eq!(Role::from(" Assistant "), Role::Assistant);
+    assert_eq!(Role::from("TOOL"), Role::Tool);
+    assert_eq!(Role::from("critic"), Role::Other("critic".to_string()));
+    assert_eq!(Role::Other("critic".to_string()).to_string(), "critic");
+    assert!(Role::User == "User");
+
+    // Serialized as plain lowercase strings, read back leniently
+    let trace: Trace = serde_json::from_value(json!({
+        "id": "t1",
+        "session_id": "s",
+        "role": "Assistant",
+        "content": "hi",
+        "created_at": "2025-01-01T00:00:00Z"
+    }))
+    .unwrap();
+    assert_eq!(trace.role, Role::Assistant);
+    assert_eq!(
+        serde_json::to_value(&trace).unwrap()["role"],
+        json!("assistant")
+    );
+
+    // Rows written before roles were normalized still decode and filter
+    let store = SqliteStore::new(":memory:").await.unwrap();
+    let history =
+        AgentHistory::with_store(store.clone(), Some("legacy")).await.unwrap();
+    let msg = Message {
+        role: "assistant".to_string(),
+        content: "legacy deploy answer".to_string(),
+    };
+    let logged = history.log_turn(&msg, HashMap::new()).await.unwrap();
+    sqlx::query("UPDATE traces SET role = 'Assistant' WHERE id = ?")
+        .bind(&logged.id)
+        .execute(store.pool())
+        .await
+        .unwrap();
+
+    let found = history
+        .query(&SearchQuery::new("deploy").role(Role::Assistant))
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].role, Role::Assistant);
+
+    let memory =
+        AgentHistory::with_store(MemoryStore::new(), Some("m")).await.unwrap();
+    memory.log_turn(&msg, HashMap::new()).await.unwrap();
+    let found =
+        memory.query(&SearchQuery::all().role("Assistant")).await.unwrap();
+    assert_eq!(found.len(), 1);
+}
+
+#[tokio::test]
+async fn test_render_session() {
+    let history = AgentHistory::new(":memory:", Some("review")).await.unwrap();
+    let model = MockModel::default();
+
+    let turns = [
+        ("system", "You are on call. </details>", json!({})),
+        ("user", "Why is <db> down?", json!({ "user_email": "a@b.c" })),
+        (
+            "assistant",
+            "Disk full.",
+            json!({
+                "duration_ms": 120,
+                "success": true,
+                "model": "mock",
+                "tag": "`x`",
+            }),
+        ),
+    ];
+    for (role, content, metadata) in turns {
+        let msg =
+            Message { role: role.to_string(), content: content.to_string() };
+        let metadata: HashMap<String, serde_json::Value> =
+            serde_json::from_value(metadata).unwrap();
+        history.log_turn(&msg, metadata).await.unwrap();
+    }
+    history.summarize_session(&model.agent()).await.unwrap();
+
+    let markdown = history
+        .render_session("review", &TranscriptOptions::default())
+        .await
+        .unwrap();
+    assert!(markdown.starts_with("# Session `review`\n\n> **Summary**"));
+    assert!(markdown.contains("### Assistant · "));
+    assert!(markdown.contains("`duration_ms: 120` `success: true`"));
+    assert!(!markdown.contains("model: mock"));
+    assert!(markdown.contains("Why is <db> down?"));
+
+    let options = TranscriptOptions::default()
+        .with_summary(false)
+        .with_timestamps(false)
+        .with_all_badges()
+        .with_redacted("user_email")
+        .with_collapsed_system(true);
+    let markdown = history.render_session("review", &options).await.unwrap();
+    assert!(!markdown.contains("Summary"));
+    assert!(markdown.contains("<details>\n<summary>System</summary>"));
+    assert!(markdown.contains("on call. &lt;/details&gt;\n\n</details>"));
+    assert!(markdown.contains("`` tag: `x` ``"));
+    assert!(markdown.contains("### User\n\n`user_email: [redacted]`"));
+    assert!(!markdown.contains("a@b.c"));
+
+    let html = history
+        .render_session("review", &options.with_format(TranscriptFormat::Html))
+        .await
+        .unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    assert!(html.contains("Why is &lt;db&gt; down?"));
+    assert!(html.contains("<details class=\"message system\">"));
+    assert!(html.contains("<span class=\"badge\">model: mock</span>"));
+
+    assert!(
+        history
+            .render_session("missing", &TranscriptOptions::default())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_import_chatgpt_export() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let export = json!([
+        {
+            "title": "Rust lifetimes",
+            "create_time": 1700000000.0,
+            "conversation_id": "conv-1",
+            "current_node": "a2",
+            "mapping": {
+                "root": { "id": "root", "message": null, "parent": null, "children": ["sys"] },
+                "sys": {
+                    "id": "sys",
+                    "message": {
+                        "id": "sys",
+                        "author": { "role": "system" },
+                        "create_time": null,
+                        "content": { "content_type": "text", "parts": [""] }
+                    },
+                    "parent": "root",
+                    "children": ["u1"]
+                },
+                "u1": {
+                    "id": "u1",
+                    "message": {
+                        "id": "u1",
+                        "author": { "role": "user" },
+                        "create_time": 1700000001.5,
+                        "content": { "content_type": "text", "parts": ["What is 'a?"] }
+                    },
+                    "parent": "sys",
+                    "children": ["a1", "a2"]
+                },
+                "a1": {
+                    "id": "a1",
+                    "message": {
+                        "id": "a1",
+                        "author": { "role": "assistant" },
+                        "create_time": 1700000002.0,
+                        "content": { "content_type": "text", "parts": ["Discarded draft"] }
+                    },
+                    "parent": "u1",
+                    "children": []
+                },
+                "a2": {
+                    "id": "a2",
+                    "message": {
+                        "id": "a2",
+                        "author": { "role": "assistant" },
+                        "create_time": 1700000003.0,
+                        "content": { "content_type": "text", "parts": ["A lifetime parameter."] },
+                        "metadata": { "model_slug": "gpt-4" },
+                        "recipient": "all"
+                    },
+                    "parent": "u1",
+                    "children": []
+                }
+            }
+        },
+        { "title": "broken", "conversation_id": "conv-2" }
+    ]);
+    std::fs::write(file.path(), export.to_string()).unwrap();
+    let path = file.path().to_str().unwrap();
+
+    let history = AgentHistory::new(":memory:", Some("main")).await.unwrap();
+    let options = ImportOptions::default();
+    let report = history.import_chatgpt(path, &options).await.unwrap();
+    assert_eq!(report.lines, 2);
+    assert_eq!(report.inserted, 2);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].line, 2);
+
+    // Only the visible branch, oldest first, with ChatGPT's IDs and times
+    let traces = history.store().session_traces("conv-1").await.unwrap();
+    let ids: Vec<&str> = traces.iter().map(|t| t.id.as_str()).collect();
+    assert_eq!(ids, ["u1", "a2"]);
+    assert_eq!(traces[0].role, "user");
+    assert_eq!(traces[0].created_at.timestamp_millis(), 1_700_000_001_500);
+    assert_eq!(traces[1].metadata["model"], json!("gpt-4"));
+    assert_eq!(
+        traces[1].metadata["conversation_title"],
+        json!("Rust lifetimes")
+    );
+    assert_eq!(traces[1].metadata["source"], json!("chatgpt"));
+
+    let report = history.import_chatgpt(path, &options).await.unwrap();
+    assert_eq!(report.skipped, 2);
+}
+
+#[tokio::test]
+async fn test_import_openai_log() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let first = json!({
+        "session_id": "weather",
+        "request": {
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "system", "content": "You are helpful." },
+                { "role": "user", "content": "Weather in Oslo?" }
+            ]
+        },
+        "response": {
+            "id": "chatcmpl-1",
+            "created": 1700000000,
+            "model": "gpt-4o",
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "get_weather", "arguments": "{\"city\":\"Oslo\"}" }
+                    }]
+                },
+                "finish_reason": "tool_calls"
+            }],
+            "usage": { "total_tokens": 42 }
+        }
+    });
+    // The second call repeats the conversation so far
+    let second = json!({
+        "session_id": "weather",
+        "request": {
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "system", "content": "You are helpful." },
+                { "role": "user", "content": "Weather in Oslo?" },
+                first["response"]["choices"][0]["message"],
+                { "role": "tool", "tool_call_id": "call_1", "content": "-3C, snow" }
+            ]
+        },
+        "response": {
+            "id": "chatcmpl-2",
+            "created": 1700000005,
+            "choices": [{
+                "message": { "role": "assistant", "content": [{ "type": "text", "text": "It's -3C and snowing." }] },
+                "finish_reason": "stop"
+            }]
+        }
+    });
+    std::fs::write(file.path(), format!("{}\n{}\n{{}}\n", first, second))
+        .unwrap();
+
+    let history = AgentHistory::new(":memory:", Some("main")).await.unwrap();
+    let report = history
+        .import_openai_log(
+            file.path().to_str().unwrap(),
+            &ImportOptions::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(report.lines, 3);
+    assert_eq!(report.inserted, 5);
+    assert_eq!(report.skipped, 3);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].line, 3);
+
+    let traces = history.store().session_traces("weather").await.unwrap();
+    let roles: Vec<&str> = traces.iter().map(|t| t.role.as_str()).collect();
+    assert_eq!(roles, ["system", "user", "assistant", "tool", "assistant"]);
+    assert_eq!(traces[2].content, "get_weather({\"city\":\"Oslo\"})");
+    assert_eq!(traces[2].metadata["tool_calls"][0]["id"], json!("call_1"));
+    assert_eq!(traces[2].metadata["tokens_used"], json!(42));
+    assert_eq!(traces[3].metadata["tool_call_id"], json!("call_1"));
+    assert_eq!(traces[4].content, "It's -3C and snowing.");
+    assert_eq!(traces[4].metadata["finish_reason"], json!("stop"));
+}
+
+#[tokio::test]
+async fn test_export_jsonl_round_trip() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let history = AgentHistory::new(":memory:", Some("a")).await.unwrap();
+    let mut other = history.clone();
+    other.switch_session("b").await.unwrap();
+
+    let mut metadata = HashMap::new();
+    metadata.insert("duration_ms".to_string(), json!(42));
+    let msg =
+        Message { role: "user".to_string(), content: "first".to_string() };
+    history.log_turn(&msg, metadata).await.unwrap();
+    let msg = Message {
+        role: "assistant".to_string(),
+        content: "second".to_string(),
+    };
+    let cutoff = history.log_turn(&msg, HashMap::new()).await.unwrap();
+    other.log_turn(&msg, HashMap::new()).await.unwrap();
+
+    // One session to a file, read back into a fresh database unchanged
+    let path = file.path().to_string_lossy().to_string();
+    let filter = ExportFilter::all().current_session();
+    assert_eq!(history.export_jsonl(&path, &filter).await.unwrap(), 2);
+
+    let restored = AgentHistory::new(":memory:", Some("a")).await.unwrap();
+    assert_eq!(restored.import_jsonl(&path).await.unwrap().inserted, 2);
+    let original: Vec<serde_json::Value> = history
+        .store()
+        .session_traces("a")
+        .await
+        .unwrap()
+        .iter()
+        .map(|t| serde_json::to_value(t).unwrap())
+        .collect();
+    let copy: Vec<serde_json::Value> = restored
+        .store()
+        .session_traces("a")
+        .await
+        .unwrap()
+        .iter()
+        .map(|t| serde_json::to_value(t).unwrap())
+        .collect();
+    assert_eq!(original, copy);
+
+    // Sets of sessions and time ranges, streamed to any writer
+    let mut out = Vec::new();
+    let filter = ExportFilter::all().sessions(["a", "b"]);
+    assert_eq!(history.write_jsonl(&mut out, &filter).await.unwrap(), 3);
+    assert_eq!(String::from_utf8(out).unwrap().lines().count(), 3);
+
+    let mut out = Vec::new();
+    let filter = ExportFilter::all().since(cutoff.created_at);
+    assert_eq!(history.write_jsonl(&mut out, &filter).await.unwrap(), 2);
+    assert!(!String::from_utf8(out).unwrap().contains("first"));
+}
+
+#[tokio::test]
+async fn test_export_jsonl_pages() {
+    let history = AgentHistory::new(":memory:", Some("big")).await.unwrap();
+    for i in 0..1203 {
+        let msg = Message {
+            role: "user".to_string(),
+            content: format!("message {}", i),
+        };
+        history.log_turn(&msg, HashMap::new()).await.unwrap();
+    }
+
+    let mut out = Vec::new();
+    let count =
+        history.write_jsonl(&mut out, &ExportFilter::all()).await.unwrap();
+    assert_eq!(count, 1203);
+
+    let ids: Vec<String> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Trace>(line).unwrap().id)
+        .collect();
+    let unique: std::collections::HashSet<&String> = ids.iter().collect();
+    assert_eq!(unique.len(), 1203);
 }
 
 #[tokio::test]
@@ -281,10 +866,13 @@ async fn test_recent_messages_conversion() {
         content: "Answer".to_string(),
     };
 
+    let failure =
+        Message { role: "error".to_string(), content: "timeout".to_string() };
     history.log_turn(&msg1, HashMap::new()).await.unwrap();
+    history.log_turn(&failure, HashMap::new()).await.unwrap();
     history.log_turn(&msg2, HashMap::new()).await.unwrap();
 
-    // Get as Messages
+    // Get as Messages, without the failed call
     let messages = history.recent_messages(10).await.unwrap();
     assert_eq!(messages.len(), 2);
     assert_eq!(messages[0].role, "user");
@@ -292,3 +880,1450 @@ async fn test_recent_messages_conversion() {
     assert_eq!(messages[1].role, "assistant");
     assert_eq!(messages[1].content, "Answer");
 }
+
+#[tokio::test]
+async fn test_list_and_get_sessions() {
+    let mut history =
+        AgentHistory::new(":memory:", Some("session-1")).await.unwrap();
+
+    let msg =
+        Message { role: "user".to_string(), content: "Hello".to_string() };
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+
+    history.switch_session("session-2").await.unwrap();
+    assert_eq!(history.session_id(), "session-2");
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+
+    let sessions = history.list_sessions().await.unwrap();
+    assert_eq!(sessions.len(), 2);
+
+    let session = history.get_session("session-2").await.unwrap().unwrap();
+    assert_eq!(session.trace_count, 2);
+    assert!(session.summary.is_none());
+
+    assert!(history.get_session("missing").await.unwrap().is_none());
+
+    // Recent traces follow the switched session
+    assert_eq!(history.recent(10).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_delete_session_removes_traces() {
+    let history = AgentHistory::new(":memory:", Some("doomed")).await.unwrap();
+
+    let msg = Message {
+        role: "user".to_string(),
+        content: "searchable content".to_string(),
+    };
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+
+    assert!(history.delete_session("doomed").await.unwrap());
+    assert!(!history.delete_session("doomed").await.unwrap());
+
+    assert!(history.get_session("doomed").await.unwrap().is_none());
+    assert_eq!(history.recent(10).await.unwrap().len(), 0);
+    assert_eq!(
+        history.search("searchable", 10, false).await.unwrap().len(),
+        0
+    );
+
+    // Logging again recreates the session
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+    let session = history.get_session("doomed").await.unwrap().unwrap();
+    assert_eq!(session.trace_count, 1);
+}
+
+#[tokio::test]
+async fn test_rename_session() {
+    let mut history =
+        AgentHistory::new(":memory:", Some("old")).await.unwrap();
+
+    let msg =
+        Message { role: "user".to_string(), content: "Hello".to_string() };
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+
+    history.rename_session("old", "new").await.unwrap();
+    assert_eq!(history.session_id(), "new");
+    assert!(history.get_session("old").await.unwrap().is_none());
+
+    let recent = history.recent(10).await.unwrap();
+    assert_eq!(recent.len(), 1);
+    assert_eq!(recent[0].session_id, "new");
+
+    assert!(history.rename_session("missing", "other").await.is_err());
+}
+
+#[tokio::test]
+async fn test_hash_embedder_is_deterministic() {
+    let embedder = HashEmbedder::new(64);
+
+    let a = embedder.embed_sync("Parse JSON in Rust");
+    let b = embedder.embed_sync("parse json in rust!");
+    assert_eq!(a.len(), 64);
+    assert!((cosine_similarity(&a, &b) - 1.0).abs() < 1e-9);
+
+    let c = embedder.embed_sync("completely unrelated words");
+    assert!(cosine_similarity(&a, &c) < 0.5);
+}
+
+#[tokio::test]
+async fn test_semantic_search() {
+    let history = AgentHistory::new(":memory:", Some("test"))
+        .await
+        .unwrap()
+        .with_embedder(HashEmbedder::default());
+
+    let messages = vec![
+        "How do I parse JSON in Rust?",
+        "The weather is sunny today",
+        "Deploy the service to production",
+    ];
+
+    for content in messages {
+        let msg =
+            Message { role: "user".to_string(), content: content.to_string() };
+        let trace = history.log_turn(&msg, HashMap::new()).await.unwrap();
+        assert!(trace.embedding_vector().is_some());
+    }
+
+    // No exact phrase match, but shares vocabulary with the first message
+    let results =
+        history.semantic_search("rust json parsing", 2).await.unwrap();
+    assert!(!results.is_empty());
+    assert!(results.len() <= 2);
+    assert_eq!(results[0].content, "How do I parse JSON in Rust?");
+}
+
+#[tokio::test]
+async fn test_semantic_search_requires_embedder() {
+    let history = AgentHistory::new(":memory:", Some("test")).await.unwrap();
+    assert!(history.semantic_search("anything", 5).await.is_err());
+}
+
+#[tokio::test]
+async fn test_failed_embedding_keeps_turn() {
+    /// Embedder whose backend is down
+    struct FailingEmbedder;
+
+    impl agentsmith::Embedder for FailingEmbedder {
+        fn embed<'a>(&'a self, _text: &'a str) -> agentsmith::EmbedFuture<'a> {
+            Box::pin(async {
+                Err(agentsmith::Error::Other("embedder down".to_string()))
+            })
+        }
+    }
+
+    let history = AgentHistory::new(":memory:", Some("test"))
+        .await
+        .unwrap()
+        .with_embedder(FailingEmbedder);
+    let msg =
+        Message { role: "user".to_string(), content: "kept".to_string() };
+    let trace = history.log_turn(&msg, HashMap::new()).await.unwrap();
+    assert!(trace.embedding.is_none());
+    assert_eq!(history.recent(1).await.unwrap()[0].id, trace.id);
+
+    // Backfilled once the embedder works again
+    let history = history.with_embedder(HashEmbedder::default());
+    assert_eq!(history.embed_missing().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_embed_missing() {
+    let history = AgentHistory::new(":memory:", Some("test")).await.unwrap();
+
+    let msg =
+        Message { role: "user".to_string(), content: "old log".to_string() };
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+
+    let history = history.with_embedder(HashEmbedder::default());
+    assert_eq!(history.embed_missing().await.unwrap(), 1);
+    assert_eq!(history.embed_missing().await.unwrap(), 0);
+
+    let results = history.semantic_search("old log", 5).await.unwrap();
+    assert_eq!(results.len(), 1);
+}
+
+#[tokio::test]
+async fn test_hybrid_retriever_fuses_rankings() {
+    let history = AgentHistory::new(":memory:", Some("test"))
+        .await
+        .unwrap()
+        .with_embedder(HashEmbedder::default());
+
+    let messages = vec![
+        "How do I parse JSON in Rust?",
+        "serde makes JSON parsing easy",
+        "The weather is sunny today",
+    ];
+    for content in messages {
+        let msg =
+            Message { role: "user".to_string(), content: content.to_string() };
+        history.log_turn(&msg, HashMap::new()).await.unwrap();
+    }
+
+    let retriever = Retriever::Hybrid(
+        HybridConfig::default().with_fts_weight(1.0).with_vector_weight(1.0),
+    );
+    let results = retriever.retrieve(&history, "JSON", 2).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|t| t.content.contains("JSON")));
+
+    // Falls back to FTS results when no embedder is configured
+    let plain = AgentHistory::new(":memory:", Some("test")).await.unwrap();
+    let msg =
+        Message { role: "user".to_string(), content: "JSON".to_string() };
+    plain.log_turn(&msg, HashMap::new()).await.unwrap();
+    let results =
+        Retriever::hybrid().retrieve(&plain, "JSON", 4).await.unwrap();
+    assert_eq!(results.len(), 1);
+}
+
+#[tokio::test]
+async fn test_smart_agent_with_semantic_retriever() {
+    let history = AgentHistory::new(":memory:", Some("test"))
+        .await
+        .unwrap()
+        .with_embedder(HashEmbedder::default());
+
+    let msg = Message {
+        role: "assistant".to_string(),
+        content: "We fixed the JSON parsing bug with serde".to_string(),
+    };
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+
+    let model = MockModel::default();
+    let mut agent = SmartAgent::new(model.agent(), history)
+        .with_retriever(Retriever::Semantic);
+
+    let reply = agent.chat("remind me about that json bug").await.unwrap();
+    assert_eq!(reply, "echo: remind me about that json bug");
+
+    let context = model.last_history();
+    assert_eq!(context.len(), 1);
+    assert_eq!(context[0].role, "system");
+    assert!(context[0].content.contains("serde"));
+}
+
+#[tokio::test]
+async fn test_search_with_special_characters() {
+    let history = AgentHistory::new(":memory:", Some("test")).await.unwrap();
+
+    let messages = vec![
+        "How do I write C++ bindings?",
+        "The foo-bar module handles \"json\" payloads",
+        "What's new in this release?",
+    ];
+    for content in messages {
+        let msg =
+            Message { role: "user".to_string(), content: content.to_string() };
+        history.log_turn(&msg, HashMap::new()).await.unwrap();
+    }
+
+    // These would all be FTS5 syntax errors if passed through verbatim
+    for query in ["what's \"json\"?", "C++", "foo-bar", "NOT", "a AND (", "*"]
+    {
+        assert!(history.search(query, 10, false).await.is_ok(), "{}", query);
+    }
+
+    let results = history.search("foo-bar", 10, false).await.unwrap();
+    assert_eq!(results.len(), 1);
+
+    // Prefix matching on longer terms
+    let results = history.search("bind", 10, false).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].content.contains("C++"));
+
+    // Pure punctuation has nothing to search for
+    assert!(history.search("?!", 10, false).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_search_raw() {
+    let history = AgentHistory::new(":memory:", Some("test")).await.unwrap();
+
+    for content in ["JSON parsing", "XML parsing", "JSON output"] {
+        let msg =
+            Message { role: "user".to_string(), content: content.to_string() };
+        history.log_turn(&msg, HashMap::new()).await.unwrap();
+    }
+
+    let results =
+        history.search_raw("parsing NOT xml", 10, false).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "JSON parsing");
+
+    // Invalid FTS5 syntax surfaces as an error
+    assert!(history.search_raw("\"unbalanced", 10, false).await.is_err());
+}
+
+#[tokio::test]
+async fn test_search_query_filters() {
+    let mut history =
+        AgentHistory::new(":memory:", Some("session-1")).await.unwrap();
+
+    for i in 0..6 {
+        let role = if i % 2 == 0 { "user" } else { "assistant" };
+        let msg = Message {
+            role: role.to_string(),
+            content: format!("deploy step {}", i),
+        };
+        let mut metadata = HashMap::new();
+        metadata.insert("duration_ms".to_string(), json!(i * 200));
+        metadata.insert("success".to_string(), json!(i != 5));
+        history.log_turn(&msg, metadata).await.unwrap();
+    }
+
+    history.switch_session("session-2").await.unwrap();
+    let msg = Message {
+        role: "assistant".to_string(),
+        content: "deploy elsewhere".to_string(),
+    };
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+
+    // Role filter
+    let results = history
+        .query(&SearchQuery::new("deploy").role("assistant").limit(10))
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 4);
+    assert!(results.iter().all(|t| t.role == "assistant"));
+
+    // Session scoping
+    let results = history
+        .query(&SearchQuery::new("deploy").current_session())
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    let results = history
+        .query(&SearchQuery::new("deploy").sessions(["session-1"]))
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 6);
+
+    // Metadata predicates pushed into SQL
+    let results = history
+        .query(&SearchQuery::new("deploy").metadata(
+            "duration_ms",
+            CompareOp::Gt,
+            json!(500),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 3);
+
+    // Success filtering happens before LIMIT
+    let results = history
+        .query(
+            &SearchQuery::new("deploy")
+                .sessions(["session-1"])
+                .metadata("duration_ms", CompareOp::Gte, json!(600))
+                .success_only(true)
+                .limit(2),
+        )
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|t| t.is_success()));
+
+    // Missing metadata compared to null
+    let results = history
+        .query(&SearchQuery::all().metadata(
+            "duration_ms",
+            CompareOp::Eq,
+            json!(null),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "deploy elsewhere");
+}
+
+#[tokio::test]
+async fn test_search_query_time_range() {
+    let history = AgentHistory::new(":memory:", Some("test")).await.unwrap();
+
+    let before = chrono::Utc::now();
+    let msg =
+        Message { role: "user".to_string(), content: "timed".to_string() };
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+    let after = chrono::Utc::now();
+
+    let results =
+        history.query(&SearchQuery::all().since(before)).await.unwrap();
+    assert_eq!(results.len(), 1);
+
+    let results = history
+        .query(
+            &SearchQuery::new("timed")
+                .since(after + chrono::Duration::seconds(1)),
+        )
+        .await
+        .unwrap();
+    assert!(results.is_empty());
+
+    let results = history
+        .query(
+            &SearchQuery::all().until(before - chrono::Duration::seconds(1)),
+        )
+        .await
+        .unwrap();
+    assert!(results.is_empty());
+}
+
+#[tokio::test]
+async fn test_memory_store_history() {
+    let mut history =
+        AgentHistory::with_store(MemoryStore::new(), Some("mem"))
+            .await
+            .unwrap();
+
+    for (role, content) in [
+        ("user", "How do I parse JSON in Rust?"),
+        ("assistant", "Use serde_json for parsing"),
+        ("user", "What about XML?"),
+    ] {
+        let msg =
+            Message { role: role.to_string(), content: content.to_string() };
+        let mut metadata = HashMap::new();
+        metadata.insert("success".to_string(), json!(role == "user"));
+        history.log_turn(&msg, metadata).await.unwrap();
+    }
+
+    let recent = history.recent(2).await.unwrap();
+    assert_eq!(recent.len(), 2);
+    assert_eq!(recent[1].content, "What about XML?");
+
+    let results = history.search("pars", 10, false).await.unwrap();
+    assert_eq!(results.len(), 2);
+    let results = history.search("json", 10, true).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].role, "user");
+
+    let results = history
+        .query(&SearchQuery::all().role("assistant").current_session())
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+
+    history.rename_session("mem", "renamed").await.unwrap();
+    let session = history.get_session("renamed").await.unwrap().unwrap();
+    assert_eq!(session.trace_count, 3);
+    assert!(history.delete_session("renamed").await.unwrap());
+    assert!(history.list_sessions().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_smart_agent_on_memory_store() {
+    let history = AgentHistory::with_store(MemoryStore::new(), Some("mem"))
+        .await
+        .unwrap();
+
+    let model = MockModel::default();
+    let mut agent = SmartAgent::new(model.agent(), history.clone());
+
+    agent.chat("remember the deploy key").await.unwrap();
+    agent.chat("what about the deploy key?").await.unwrap();
+
+    // The second turn recalls the first from the shared in-memory store
+    let context = model.last_history();
+    assert_eq!(context.len(), 1);
+    assert!(context[0].content.contains("remember the deploy key"));
+    assert_eq!(history.recent(10).await.unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn test_traces_after_same_timestamp() {
+    // Traces sharing the checkpoint's timestamp keep their insertion order
+    let created_at = chrono::Utc::now();
+    let traces: Vec<Trace> = ["c", "a", "b"]
+        .iter()
+        .map(|id| Trace {
+            id: id.to_string(),
+            created_at,
+            ..Trace::new("tie".to_string(), "user", id.to_string())
+        })
+        .collect();
+
+    let sqlite = AgentHistory::new(":memory:", Some("tie")).await.unwrap();
+    let memory = AgentHistory::with_store(MemoryStore::new(), Some("tie"))
+        .await
+        .unwrap();
+    for history in [sqlite, memory] {
+        let store = history.store();
+        store
+            .import_traces(&traces, &[(0, 0); 3], DuplicateStrategy::Skip)
+            .await
+            .unwrap();
+
+        let after = store.traces_after("tie", Some("c")).await.unwrap();
+        let ids: Vec<&str> = after.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+        let after = store.traces_after("tie", Some("a")).await.unwrap();
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].id, "b");
+        let all = store.traces_after("tie", None).await.unwrap();
+        assert_eq!(all.len(), 3);
+    }
+}
+
+#[tokio::test]
+async fn test_incremental_summary() {
+    let history = AgentHistory::new(":memory:", Some("inc")).await.unwrap();
+    let model = MockModel::default();
+    let summarizer = model.agent();
+
+    for (role, content) in [("user", "I use Postgres"), ("assistant", "Noted")]
+    {
+        let msg =
+            Message { role: role.to_string(), content: content.to_string() };
+        history.log_turn(&msg, HashMap::new()).await.unwrap();
+    }
+
+    let first = history.summarize_session(&summarizer).await.unwrap();
+    assert!(first.starts_with("echo: Please provide a concise summary"));
+    assert!(first.contains("user: I use Postgres"));
+
+    let last = history.recent(1).await.unwrap().pop().unwrap();
+    let session = history.get_session("inc").await.unwrap().unwrap();
+    assert_eq!(session.summary_checkpoint, Some(last.id));
+
+    // Nothing new since the checkpoint: no model call
+    let again = history.summarize_session(&summarizer).await.unwrap();
+    assert_eq!(again, first);
+    assert_eq!(model.request_count(), 1);
+
+    let msg = Message {
+        role: "user".to_string(),
+        content: "Actually I switched to SQLite".to_string(),
+    };
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+
+    // Only the new turn is sent, along with the previous summary
+    let second = history.summarize_session(&summarizer).await.unwrap();
+    assert!(second.starts_with("echo: Here is a summary of a conversation"));
+    assert!(second.contains(&first));
+    assert!(second.contains("user: Actually I switched to SQLite"));
+    assert_eq!(second.matches("user: I use Postgres").count(), 1);
+}
+
+#[tokio::test]
+async fn test_map_reduce_first_summary() {
+    let history = AgentHistory::with_store(MemoryStore::new(), Some("big"))
+        .await
+        .unwrap()
+        .with_summary_config(SummaryConfig::default().with_chunk_chars(40));
+    let model = MockModel::default();
+
+    for i in 0..4 {
+        let msg = Message {
+            role: "user".to_string(),
+            content: format!("message number {} of the session", i),
+        };
+        history.log_turn(&msg, HashMap::new()).await.unwrap();
+    }
+
+    // One call per chunk, then one to combine them
+    let summary = history.summarize_session(&model.agent()).await.unwrap();
+    assert_eq!(model.request_count(), 5);
+    assert!(summary.starts_with("echo: Combine these summaries"));
+    assert!(summary.contains(
+        "Part 4:\necho: Please provide a concise summary of part 4 of 4"
+    ));
+}
+
+#[tokio::test]
+async fn test_summary_versions() {
+    let mut history =
+        AgentHistory::new(":memory:", Some("versions")).await.unwrap();
+    let model = MockModel::default();
+    let summarizer = model.agent();
+
+    for content in ["we deploy with kubernetes", "now we deploy with nomad"] {
+        let msg =
+            Message { role: "user".to_string(), content: content.to_string() };
+        let trace = history.log_turn(&msg, HashMap::new()).await.unwrap();
+        history.summarize_session(&summarizer).await.unwrap();
+
+        let latest =
+            history.latest_summary("versions").await.unwrap().unwrap();
+        assert_eq!(latest.first_trace_id, Some(trace.id.clone()));
+        assert_eq!(latest.last_trace_id, Some(trace.id));
+        assert_eq!(latest.model, None);
+    }
+
+    // Earlier versions are kept, oldest first
+    let summaries = history.list_summaries("versions").await.unwrap();
+    assert_eq!(summaries.len(), 2);
+    assert!(summaries[0].content.contains("kubernetes"));
+    assert!(summaries[1].content.starts_with("echo: Here is a summary"));
+    let session = history.get_session("versions").await.unwrap().unwrap();
+    assert_eq!(session.summary.as_ref(), Some(&summaries[1].content));
+
+    let found = history.search_summaries("nomad", 10).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, summaries[1].id);
+
+    // Summaries written in the same instant keep their order
+    let created_at = chrono::Utc::now();
+    for (id, content) in [("tie-a", "first"), ("tie-b", "second")] {
+        let mut summary =
+            SessionSummary::new("versions".to_string(), content.to_string());
+        summary.id = id.to_string();
+        summary.created_at = created_at;
+        summary.model = Some("mock-1".to_string());
+        history.store().add_summary(&summary).await.unwrap();
+    }
+    let summaries = history.list_summaries("versions").await.unwrap();
+    assert_eq!(summaries[2].content, "first");
+    assert_eq!(summaries[3].content, "second");
+    let latest = history.latest_summary("versions").await.unwrap().unwrap();
+    assert_eq!(latest.id, "tie-b");
+    assert_eq!(latest.model.as_deref(), Some("mock-1"));
+
+    // Summaries follow their session
+    history.rename_session("versions", "renamed").await.unwrap();
+    assert_eq!(history.list_summaries("renamed").await.unwrap().len(), 4);
+    history.delete_session("renamed").await.unwrap();
+    assert!(history.search_summaries("nomad", 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_summary_search_after_edit() {
+    let store = SqliteStore::new(":memory:").await.unwrap();
+    let history =
+        AgentHistory::with_store(store.clone(), Some("edit")).await.unwrap();
+    let summary =
+        SessionSummary::new("edit".to_string(), "alpha bravo".to_string());
+    history.store().add_summary(&summary).await.unwrap();
+
+    // Edited summaries are found by their new text only
+    sqlx::query("UPDATE summaries SET content = 'charlie delta' WHERE id = ?")
+        .bind(&summary.id)
+        .execute(store.pool())
+        .await
+        .unwrap();
+    assert!(history.search_summaries("alpha", 10).await.unwrap().is_empty());
+    let found = history.search_summaries("charlie", 10).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, summary.id);
+}
+
+/// Connect to the throwaway database in `AGENTSMITH_POSTGRES_URL`, if set
+#[cfg(feature = "postgres")]
+async fn postgres_history(session_id: &str) -> Option<AgentHistory> {
+    let url = std::env::var("AGENTSMITH_POSTGRES_URL").ok()?;
+    let store = agentsmith::PostgresStore::new(&url).await.unwrap();
+    Some(AgentHistory::with_store(store, Some(session_id)).await.unwrap())
+}
+
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn test_postgres_log_search_and_sessions() {
+    let session_id = format!("pg-{}", uuid::Uuid::new_v4());
+    let Some(mut history) = postgres_history(&session_id).await else {
+        return;
+    };
+
+    for (role, content, duration) in [
+        ("user", "How do I parse JSON in Rust?", 100),
+        ("assistant", "Use serde_json for parsing", 900),
+        ("user", "What about C++?", 50),
+    ] {
+        let msg =
+            Message { role: role.to_string(), content: content.to_string() };
+        let mut metadata = HashMap::new();
+        metadata.insert("duration_ms".to_string(), json!(duration));
+        metadata.insert("success".to_string(), json!(role == "user"));
+        history.log_turn(&msg, metadata).await.unwrap();
+    }
+
+    let recent = history.recent(10).await.unwrap();
+    assert_eq!(recent.len(), 3);
+    assert_eq!(recent[0].content, "How do I parse JSON in Rust?");
+
+    let scoped = SearchQuery::new("parsing").current_session();
+    assert_eq!(history.query(&scoped).await.unwrap().len(), 2);
+
+    let query = SearchQuery::new("c++ json")
+        .current_session()
+        .metadata("duration_ms", CompareOp::Gt, json!(75))
+        .success_only(true);
+    let results = history.query(&query).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, "How do I parse JSON in Rust?");
+
+    let new_id = format!("{}-renamed", session_id);
+    history.rename_session(&session_id, &new_id).await.unwrap();
+    let session = history.get_session(&new_id).await.unwrap().unwrap();
+    assert_eq!(session.trace_count, 3);
+
+    // Summary checkpoints survive the rename and gate the next summary
+    let model = MockModel::default();
+    history.summarize_session(&model.agent()).await.unwrap();
+    history.summarize_session(&model.agent()).await.unwrap();
+    assert_eq!(model.request_count(), 1);
+    let session = history.get_session(&new_id).await.unwrap().unwrap();
+    assert_eq!(session.summary_checkpoint, Some(recent[2].id.clone()));
+    let summaries = history.list_summaries(&new_id).await.unwrap();
+    assert_eq!(summaries.len(), 1);
+    let found = history.search_summaries("serde_json", 100).await.unwrap();
+    assert!(found.iter().any(|s| s.id == summaries[0].id));
+
+    // Exports page through the session in order
+    let mut out = Vec::new();
+    let filter = ExportFilter::all().sessions([new_id.clone()]);
+    assert_eq!(history.write_jsonl(&mut out, &filter).await.unwrap(), 3);
+    let exported: Vec<Trace> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(exported[2].id, recent[2].id);
+
+    // Re-importing skips what's there and creates missing sessions
+    let moved_id = format!("{}-moved", session_id);
+    let moved = Trace { session_id: moved_id.clone(), ..exported[0].clone() };
+    let jsonl = format!(
+        "{}\n{}\n",
+        serde_json::to_string(&exported[1]).unwrap(),
+        serde_json::to_string(&Trace {
+            id: uuid::Uuid::new_v4().to_string(),
+            ..moved
+        })
+        .unwrap()
+    );
+    let report = history
+        .read_jsonl(jsonl.as_bytes(), &ImportOptions::default())
+        .await
+        .unwrap();
+    assert_eq!((report.inserted, report.skipped), (1, 1));
+    assert!(history.delete_session(&moved_id).await.unwrap());
+
+    // SmartAgent state round-trips through JSONB
+    let state = json!({ "turn_count": 7, "retriever": "full_text" });
+    history.store().set_agent_state(&new_id, &state).await.unwrap();
+    let session = history.get_session(&new_id).await.unwrap().unwrap();
+    assert_eq!(session.agent_state, Some(state));
+
+    assert!(history.delete_session(&new_id).await.unwrap());
+    assert!(history.get_session(&new_id).await.unwrap().is_none());
+}
+
+fn trace(role: &str, content: &str) -> Trace {
+    Trace::new("test".to_string(), role.to_string(), content.to_string())
+}
+
+#[test]
+fn test_context_builder_respects_budget() {
+    let counter = CharHeuristic::default();
+    let builder = ContextBuilder::new(200);
+
+    let recent = vec![
+        trace("user", &"old turn ".repeat(20)),
+        trace("assistant", "latest answer"),
+    ];
+    let recalled = vec![
+        trace("assistant", "short recalled fact"),
+        trace("assistant", &"very long recalled trace ".repeat(100)),
+        trace("user", "never reached"),
+    ];
+
+    let window = builder.build(
+        Some(&"summary text ".repeat(50)),
+        &[],
+        &recent,
+        &recalled,
+        10,
+    );
+
+    // Everything rendered stays within the budget minus the reserved tokens
+    let rendered: usize = window
+        .to_messages()
+        .iter()
+        .map(|m| counter.count(&m.content) + counter.count(&m.role))
+        .sum();
+    assert!(window.tokens_used <= 190);
+    assert!(rendered <= 190 + 10);
+
+    // The summary is truncated to its share rather than dropped
+    let summary = window.summary.as_ref().unwrap();
+    assert!(summary.ends_with('…'));
+
+    // The newest recent turn is always kept, in chronological order
+    assert_eq!(window.recent.last().unwrap().content, "latest answer");
+
+    // Recall keeps the best match and truncates the long one to fit
+    assert_eq!(window.recalled[0].content, "short recalled fact");
+    assert!(window.recalled.len() <= 2);
+    assert!(window.recalled.iter().all(|t| t.content != "never reached"));
+}
+
+#[test]
+fn test_context_builder_budget_used_up() {
+    // The user's message alone takes more than the whole budget
+    let builder = ContextBuilder::new(4);
+    let window = builder.build(Some(""), &[], &[], &[], 10);
+    assert!(window.is_empty());
+    assert_eq!(window.tokens_used, 0);
+
+    let recent = vec![trace("user", ""), trace("assistant", "hi")];
+    let window = builder.build(Some(""), &[], &recent, &recent, 4);
+    assert!(window.is_empty());
+    assert_eq!(window.tokens_used, 0);
+
+    // Empty items are skipped without charging their headers
+    let window =
+        ContextBuilder::new(100).build(Some(""), &[], &recent, &[], 0);
+    assert!(window.summary.is_none());
+    assert_eq!(window.recent.len(), 1);
+    assert_eq!(window.recent[0].content, "hi");
+}
+
+#[test]
+fn test_context_builder_custom_token_counter() {
+    /// Counts whitespace-separated words
+    struct WordCounter;
+
+    impl TokenCounter for WordCounter {
+        fn count(&self, text: &str) -> usize {
+            text.split_whitespace().count()
+        }
+    }
+
+    let builder = ContextBuilder::new(40)
+        .with_token_counter(WordCounter)
+        .with_summary_share(0.0)
+        .with_recent_share(0.0);
+
+    let recalled: Vec<Trace> = (0..10)
+        .map(|i| trace("user", &format!("fact number {}", i)))
+        .collect();
+    let window = builder.build(None, &[], &[], &recalled, 0);
+
+    // Each line costs 3 words of prefix plus 3 of content
+    assert!(!window.recalled.is_empty());
+    assert!(window.recalled.len() < 10);
+    assert!(window.tokens_used <= 40);
+}
+
+#[tokio::test]
+async fn test_smart_agent_context_budget() {
+    let history = AgentHistory::with_store(MemoryStore::new(), Some("mem"))
+        .await
+        .unwrap();
+
+    for i in 0..4 {
+        let msg = Message {
+            role: "assistant".to_string(),
+            content: format!("deploy notes {} {}", i, "padding ".repeat(200)),
+        };
+        history.log_turn(&msg, HashMap::new()).await.unwrap();
+    }
+
+    let model = MockModel::default();
+    let mut agent = SmartAgent::new(model.agent(), history.clone())
+        .with_context_budget(300);
+    agent.chat("deploy").await.unwrap();
+
+    let context = model.last_history();
+    assert_eq!(context.len(), 1);
+    assert!(CharHeuristic::default().count(&context[0].content) <= 300);
+
+    let user_trace = &history.recent(2).await.unwrap()[0];
+    let injected =
+        user_trace.get_metadata("recalled_traces").unwrap().as_u64().unwrap();
+    assert!((1..4).contains(&injected));
+}
+
+#[tokio::test]
+async fn test_smart_agent_recent_turns() {
+    let history = AgentHistory::with_store(MemoryStore::new(), Some("mem"))
+        .await
+        .unwrap();
+
+    let model = MockModel::default();
+    let mut agent =
+        SmartAgent::new(model.agent(), history.clone()).with_recent_turns(4);
+
+    agent.chat("my name is Ada").await.unwrap();
+    agent.chat("what is my name?").await.unwrap();
+
+    // Previous user and assistant turns come through as chat history
+    let context = model.last_history();
+    let turns: Vec<(&str, &str)> = context
+        .iter()
+        .filter(|m| m.role != "system")
+        .map(|m| (m.role.as_str(), m.content.as_str()))
+        .collect();
+    assert_eq!(
+        turns,
+        vec![
+            ("user", "my name is Ada"),
+            ("assistant", "echo: my name is Ada")
+        ]
+    );
+
+    // Turns already in the recent window are not recalled again
+    assert!(
+        context
+            .iter()
+            .all(|m| m.role != "system"
+                || !m.content.contains("my name is Ada"))
+    );
+
+    // A restarted agent on the same store picks the conversation back up
+    let model = MockModel::default();
+    let mut agent =
+        SmartAgent::new(model.agent(), history.clone()).with_recent_turns(2);
+    agent.chat("and again?").await.unwrap();
+    let context = model.last_history();
+    let last = context.last().unwrap();
+    assert_eq!(last.role, "assistant");
+    assert_eq!(last.content, "echo: what is my name?");
+}
+
+#[tokio::test]
+async fn test_recall_options() {
+    let store = MemoryStore::new();
+    let turns = [
+        ("old-1", "user", "How do I reset the router?"),
+        ("old-1", "assistant", "Hold the button for ten seconds."),
+        ("old-2", "user", "how do i RESET the router"),
+        ("old-2", "assistant", "Unplug it and wait."),
+        ("current", "user", "My router needs a reset"),
+        ("current", "assistant", "Which model is it?"),
+    ];
+    for (session, role, content) in turns {
+        let history = AgentHistory::with_store(store.clone(), Some(session))
+            .await
+            .unwrap();
+        let msg =
+            Message { role: role.to_string(), content: content.to_string() };
+        history.log_turn(&msg, HashMap::new()).await.unwrap();
+    }
+
+    let history =
+        AgentHistory::with_store(store, Some("current")).await.unwrap();
+    let retriever = Retriever::FullText;
+
+    // Without options the current session's own turns are recalled
+    let plain = retriever
+        .recall(&history, "reset router", 10, &RecallOptions::default())
+        .await
+        .unwrap();
+    assert!(plain.iter().any(|t| t.session_id == "current"));
+
+    // Excluding recent turns drops the current session's echoes
+    let options = RecallOptions::default().with_exclude_recent(2);
+    let results = retriever
+        .recall(&history, "reset router", 10, &options)
+        .await
+        .unwrap();
+    assert!(!results.is_empty());
+    assert!(results.iter().all(|t| t.session_id != "current"));
+
+    // Normalized dedupe collapses the repeated question; pairing brings
+    // the matching answer along
+    let options =
+        options.with_dedupe(Dedupe::Normalized).with_pair_answers(true);
+    let results =
+        retriever.recall(&history, "reset router", 1, &options).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].role, "user");
+    assert_eq!(results[1].role, "assistant");
+    assert_eq!(results[0].session_id, results[1].session_id);
+
+    let results = retriever
+        .recall(&history, "reset router", 10, &options)
+        .await
+        .unwrap();
+    let questions: Vec<&Trace> =
+        results.iter().filter(|t| t.role == "user").collect();
+    assert_eq!(questions.len(), 1);
+    assert_eq!(results.len(), 2);
+}
+
+#[tokio::test]
+async fn test_smart_agent_background_summary() {
+    let history = AgentHistory::new(":memory:", Some("bg")).await.unwrap();
+
+    let summarizer = MockModel::default();
+    let mut agent = SmartAgent::new(MockModel::default().agent(), history)
+        .with_summarize_every(1)
+        .with_summarizer(summarizer.agent());
+
+    // Nothing to wait for before the first trigger
+    assert!(agent.wait_for_summary().await.unwrap().is_none());
+
+    agent.chat("remember the milk").await.unwrap();
+    let summary = agent.wait_for_summary().await.unwrap().unwrap();
+    assert!(summary.starts_with("echo: Please provide a concise summary"));
+
+    let status = agent.summary_status();
+    assert!(!status.pending);
+    assert!(status.last_error.is_none());
+    assert!(status.last_summarized_at.is_some());
+
+    let session = agent.history().get_session("bg").await.unwrap().unwrap();
+    assert_eq!(session.summary.as_deref(), Some(summary.as_str()));
+    assert!(!agent.cancel_summary());
+}
+
+#[tokio::test]
+async fn test_smart_agent_summary_errors_are_surfaced() {
+    let history = AgentHistory::new(":memory:", Some("bg")).await.unwrap();
+
+    let mut agent = SmartAgent::new(MockModel::default().agent(), history)
+        .with_summarize_every(1)
+        .with_summarizer(AgentBuilder::new(FailingModel).build());
+
+    // The chat itself succeeds even though summarization fails
+    agent.chat("hello").await.unwrap();
+    assert!(agent.wait_for_summary().await.is_err());
+
+    let status = agent.summary_status();
+    assert!(status.last_error.unwrap().contains("service unavailable"));
+    assert!(status.last_summarized_at.is_none());
+}
+
+#[tokio::test]
+async fn test_smart_agent_injects_summaries() {
+    let store = MemoryStore::new();
+    let model = MockModel::default();
+
+    // A past session about deployments, already summarized
+    let past =
+        AgentHistory::with_store(store.clone(), Some("past")).await.unwrap();
+    let msg = Message {
+        role: "user".to_string(),
+        content: "we deploy with kubernetes".to_string(),
+    };
+    past.log_turn(&msg, HashMap::new()).await.unwrap();
+    past.summarize_session(&model.agent()).await.unwrap();
+
+    let current =
+        AgentHistory::with_store(store, Some("current")).await.unwrap();
+    let msg = Message {
+        role: "user".to_string(),
+        content: "my name is Ada".to_string(),
+    };
+    current.log_turn(&msg, HashMap::new()).await.unwrap();
+    let summary = current.summarize_session(&model.agent()).await.unwrap();
+
+    let mut agent = SmartAgent::new(model.agent(), current.clone())
+        .with_recall_top_k(0)
+        .with_related_summaries(2);
+    agent.chat("how do we deploy kubernetes?").await.unwrap();
+
+    let context = model.last_history();
+    assert_eq!(context.len(), 2);
+    assert_eq!(context[0].role, "system");
+    assert!(context[0].content.starts_with("Summary of this conversation"));
+    assert!(context[0].content.ends_with(&summary));
+    assert!(context[1].content.starts_with("Summaries of related past"));
+    assert!(context[1].content.contains("session past: "));
+    assert!(!context[1].content.contains("session current: "));
+
+    // Both can be turned off
+    let mut agent = SmartAgent::new(model.agent(), current)
+        .with_recall_top_k(0)
+        .with_session_summary(false);
+    agent.chat("how do we deploy kubernetes?").await.unwrap();
+    assert!(model.last_history().is_empty());
+}
+
+#[tokio::test]
+async fn test_summary_templates() {
+    let config = SummaryConfig::default()
+        .with_initial_template(
+            "Session {session_id} ({unknown}):\n{transcript}",
+        )
+        .with_role_format("assistant", "AI said> {content}")
+        .with_max_words(50);
+    let history = AgentHistory::with_store(MemoryStore::new(), Some("tpl"))
+        .await
+        .unwrap()
+        .with_summary_config(config);
+
+    for (role, content) in [
+        ("user", "what does {transcript} do?"),
+        ("assistant", "It's a placeholder"),
+    ] {
+        let msg =
+            Message { role: role.to_string(), content: content.to_string() };
+        history.log_turn(&msg, HashMap::new()).await.unwrap();
+    }
+
+    let summary = history
+        .summarize_session(&MockModel::default().agent())
+        .await
+        .unwrap();
+    assert_eq!(
+        summary,
+        "echo: Session tpl ({unknown}):\n\
+         user: what does {transcript} do?\n\
+         AI said> It's a placeholder\n\n\
+         Keep the summary under 50 words."
+    );
+}
+
+#[tokio::test]
+async fn test_structured_summary() {
+    let history = AgentHistory::with_store(MemoryStore::new(), Some("json"))
+        .await
+        .unwrap()
+        .with_summary_config(
+            SummaryConfig::default().with_format(SummaryFormat::structured()),
+        );
+    let msg = Message {
+        role: "user".to_string(),
+        content: "We picked SQLite".to_string(),
+    };
+    history.log_turn(&msg, HashMap::new()).await.unwrap();
+
+    // The format instructions are appended to the prompt
+    let echoed = history
+        .summarize_session(&MockModel::default().agent())
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(echoed.contains("Respond with only a JSON object"));
+
+    // Fenced JSON is unwrapped and missing keys are filled in
+    let reply = "```json\n{\"decisions\": [\"use SQLite\"]}\n```";
+    let summary =
+        history.summarize_session(&FixedModel::agent(reply)).await.unwrap();
+    let summary: serde_json::Value = serde_json::from_str(&summary).unwrap();
+    assert_eq!(
+        summary,
+        json!({"decisions": ["use SQLite"], "open_questions": [], "facts": []})
+    );
+}
+
+#[tokio::test]
+async fn test_size_based_summary_trigger_survives_restart() {
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let path = file.path();
+    let model = MockModel::default();
+
+    {
+        let history = AgentHistory::new(path, Some("s")).await.unwrap();
+        let mut agent = SmartAgent::new(model.agent(), history)
+            .with_summary_trigger(SummaryTrigger::Chars(100));
+        agent.chat("short").await.unwrap();
+    }
+
+    // "short" + "echo: short", counted with the default 4 chars per token
+    let history = AgentHistory::new(path, Some("s")).await.unwrap();
+    let session = history.get_session("s").await.unwrap().unwrap();
+    assert_eq!(session.unsummarized_chars, 16);
+    assert_eq!(session.unsummarized_tokens, 5);
+    assert!(session.summary.is_none());
+
+    // After a restart the stored count carries on; one large turn tips it
+    let mut agent = SmartAgent::new(model.agent(), history)
+        .with_summary_trigger(SummaryTrigger::Chars(100));
+    agent.chat(&"x".repeat(50)).await.unwrap();
+    agent.wait_for_summary().await.unwrap().unwrap();
+
+    let session = agent.history().get_session("s").await.unwrap().unwrap();
+    assert!(session.summary.is_some());
+    assert_eq!(session.unsummarized_chars, 0);
+    assert_eq!(session.unsummarized_tokens, 0);
+}
+
+#[tokio::test]
+async fn test_smart_agent_resume() {
+    let store = MemoryStore::new();
+    let model = MockModel::default();
+
+    let history =
+        AgentHistory::with_store(store.clone(), Some("r")).await.unwrap();
+    let mut agent = SmartAgent::new(model.agent(), history)
+        .with_recall_top_k(1)
+        .with_summarize_every(3);
+    agent.chat("first").await.unwrap();
+    agent.chat("second").await.unwrap();
+    drop(agent);
+
+    // Turn count and summary cadence carry over
+    let history =
+        AgentHistory::with_store(store.clone(), Some("r")).await.unwrap();
+    let mut agent = SmartAgent::resume(model.agent(), history).await.unwrap();
+    assert_eq!(agent.turn_count(), 2);
+    assert!(agent.summary_status().checkpoint.is_none());
+
+    agent.chat("third").await.unwrap();
+    assert_eq!(agent.turn_count(), 3);
+    agent.wait_for_summary().await.unwrap().unwrap();
+    let session = agent.history().get_session("r").await.unwrap().unwrap();
+    assert!(session.summary.is_some());
+    let checkpoint = agent.summary_status().checkpoint;
+    assert_eq!(checkpoint, session.summary_checkpoint);
+    drop(agent);
+
+    // The summary status is restored too, and builders override the config
+    let history =
+        AgentHistory::with_store(store.clone(), Some("r")).await.unwrap();
+    let mut agent = SmartAgent::resume(model.agent(), history)
+        .await
+        .unwrap()
+        .with_summarize_every(100);
+    let status = agent.summary_status();
+    assert_eq!(status.checkpoint, checkpoint);
+    assert!(status.last_summarized_at.is_some());
+
+    agent.chat("fourth").await.unwrap();
+    let session = agent.history().get_session("r").await.unwrap().unwrap();
+    assert_eq!(session.summary_checkpoint, checkpoint);
+    let state = session.agent_state.unwrap();
+    assert_eq!(state["turn_count"], 4);
+    assert_eq!(state["recall_top_k"], 1);
+    assert_eq!(state["summary_trigger"], json!({ "turns": 100 }));
+
+    // Sessions without saved state count user and assistant pairs
+    let history =
+        AgentHistory::with_store(store.clone(), Some("plain")).await.unwrap();
+    for (role, content) in [("user", "hi"), ("assistant", "hello")] {
+        let msg =
+            Message { role: role.to_string(), content: content.to_string() };
+        history.log_turn(&msg, HashMap::new()).await.unwrap();
+    }
+    let agent = SmartAgent::resume(model.agent(), history).await.unwrap();
+    assert_eq!(agent.turn_count(), 1);
+
+    // Unknown sessions start fresh
+    let history = AgentHistory::with_store(store, Some("new")).await.unwrap();
+    let agent = SmartAgent::resume(model.agent(), history).await.unwrap();
+    assert_eq!(agent.turn_count(), 0);
+}
+
+#[tokio::test]
+async fn test_smart_agent_chat_stream() {
+    let store = MemoryStore::new();
+    let model = MockModel::default();
+
+    // Without a streamer the whole reply arrives as one chunk
+    let history =
+        AgentHistory::with_store(store.clone(), Some("s")).await.unwrap();
+    let mut agent = SmartAgent::new(model.agent(), history);
+    let chunks: Vec<String> = agent
+        .chat_stream("hello")
+        .await
+        .unwrap()
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    assert_eq!(chunks, vec!["echo: hello".to_string()]);
+    assert_eq!(agent.turn_count(), 1);
+
+    let history =
+        AgentHistory::with_store(store.clone(), Some("s")).await.unwrap();
+    let mut agent =
+        SmartAgent::new(model.agent(), history).with_streamer(|prompt, _| {
+            let words: Vec<agentsmith::Result<String>> = prompt
+                .split_inclusive(' ')
+                .map(|word| Ok(word.to_string()))
+                .collect();
+            Box::pin(stream::iter(words)) as ChunkStream
+        });
+
+    // A finished stream logs the whole response
+    let chunks: Vec<String> = agent
+        .chat_stream("one two three")
+        .await
+        .unwrap()
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    assert_eq!(chunks, vec!["one ", "two ", "three"]);
+    let recent = agent.history().recent(1).await.unwrap();
+    assert_eq!(recent[0].content, "one two three");
+    assert_eq!(recent[0].metadata["success"], json!(true));
+
+    // Dropping the stream early logs the partial response
+    {
+        let mut stream = agent.chat_stream("four five six").await.unwrap();
+        assert_eq!(stream.next().await.unwrap().unwrap(), "four ");
+
+        // The user turn is logged before the response completes
+        let observer =
+            AgentHistory::with_store(store.clone(), Some("s")).await.unwrap();
+        let recent = observer.recent(1).await.unwrap();
+        assert_eq!(recent[0].role, "user");
+    }
+    assert_eq!(agent.turn_count(), 1);
+
+    // ...ahead of the next user message, keeping the session in order
+    agent.chat("again").await.unwrap();
+    let traces = agent.history().store().session_traces("s").await.unwrap();
+    let tail: Vec<(&str, &str)> = traces[traces.len() - 4..]
+        .iter()
+        .map(|t| (t.role.as_str(), t.content.as_str()))
+        .collect();
+    assert_eq!(
+        tail,
+        [
+            ("user", "four five six"),
+            ("assistant", "four "),
+            ("user", "again"),
+            ("assistant", "echo: again"),
+        ]
+    );
+    let partial = &traces[traces.len() - 3];
+    assert_eq!(partial.metadata["success"], json!(false));
+    assert_eq!(partial.metadata["interrupted"], json!(true));
+
+    // Stream errors are yielded and logged with what arrived before them
+    let history = AgentHistory::with_store(store, Some("s")).await.unwrap();
+    let mut agent =
+        SmartAgent::new(model.agent(), history).with_streamer(|_, _| {
+            Box::pin(stream::iter(vec![
+                Ok("half".to_string()),
+                Err(agentsmith::Error::Rig("connection reset".to_string())),
+            ])) as ChunkStream
+        });
+    let results: Vec<_> =
+        agent.chat_stream("seven").await.unwrap().collect().await;
+    assert_eq!(results.len(), 2);
+    assert!(results[1].is_err());
+    let recent = agent.history().recent(1).await.unwrap();
+    assert_eq!(recent[0].content, "half");
+    assert_eq!(recent[0].metadata["interrupted"], json!(true));
+    assert!(
+        recent[0].metadata["error"]
+            .as_str()
+            .unwrap()
+            .contains("connection reset")
+    );
+}
+
+#[tokio::test]
+async fn test_smart_agent_logs_failed_calls() {
+    let history = AgentHistory::with_store(MemoryStore::new(), Some("fail"))
+        .await
+        .unwrap();
+    let mut agent =
+        SmartAgent::new(AgentBuilder::new(FailingModel).build(), history);
+
+    assert!(agent.chat("are you there?").await.is_err());
+    assert_eq!(agent.turn_count(), 0);
+
+    let traces = agent.history().recent(10).await.unwrap();
+    assert_eq!(traces.len(), 2);
+    assert_eq!(traces[0].role, "user");
+    let error = &traces[1];
+    assert_eq!(error.role, "error");
+    assert!(error.content.contains("service unavailable"));
+    assert_eq!(error.metadata["success"], json!(false));
+    assert_eq!(error.metadata["error_kind"], json!("provider"));
+    assert_eq!(error.metadata["retries"], json!(0));
+    assert!(error.metadata["duration_ms"].is_number());
+
+    // Failures are searchable but excluded from successful traces
+    let history = agent.history();
+    assert_eq!(
+        history.search("unavailable", 10, false).await.unwrap().len(),
+        1
+    );
+    assert!(history.search("unavailable", 10, true).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_smart_agent_retry_and_fallback() {
+    let fast = RetryPolicy::default()
+        .with_max_attempts(3)
+        .with_backoff(std::time::Duration::from_millis(1), 2.0);
+
+    // Two transient failures, then success on the third attempt
+    let model = FlakyModel::new(2);
+    let history = AgentHistory::with_store(MemoryStore::new(), Some("retry"))
+        .await
+        .unwrap();
+    let mut agent = SmartAgent::new(model.agent(), history)
+        .with_retry_policy(fast.clone());
+    assert_eq!(agent.chat("hello").await.unwrap(), "recovered");
+    assert_eq!(model.calls(), 3);
+
+    let reply = agent.history().recent(1).await.unwrap().remove(0);
+    assert_eq!(reply.metadata["retries"], json!(2));
+    assert_eq!(reply.metadata["fallback"], json!(false));
+    let attempts = reply.metadata["attempts"].as_array().unwrap();
+    assert_eq!(attempts.len(), 3);
+    assert_eq!(attempts[0]["error_kind"], json!("provider"));
+    assert_eq!(attempts[0]["success"], json!(false));
+    assert_eq!(attempts[2]["attempt"], json!(3));
+    assert_eq!(attempts[2]["success"], json!(true));
+
+    // The primary gives up, so the fallback answers
+    let model = FlakyModel::new(2);
+    let history = AgentHistory::with_store(MemoryStore::new(), Some("fb"))
+        .await
+        .unwrap();
+    let mut agent =
+        SmartAgent::new(AgentBuilder::new(FailingModel).build(), history)
+            .with_retry_policy(fast.clone().with_max_attempts(2))
+            .with_fallback(model.agent());
+    assert!(agent.chat("hello").await.is_err());
+    assert_eq!(model.calls(), 2);
+
+    let error = agent.history().recent(1).await.unwrap().remove(0);
+    assert_eq!(error.role, "error");
+    assert_eq!(error.metadata["retries"], json!(2));
+    assert_eq!(error.metadata["fallback"], json!(true));
+
+    assert_eq!(agent.chat("again").await.unwrap(), "recovered");
+    let reply = agent.history().recent(1).await.unwrap().remove(0);
+    let agents: Vec<&str> = reply.metadata["attempts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|a| a["agent"].as_str().unwrap())
+        .collect();
+    assert_eq!(agents, vec!["primary", "primary", "fallback"]);
+    assert_eq!(reply.metadata["retries"], json!(1));
+
+    // Retries are counted per agent, the fallback's included
+    let model = FlakyModel::new(1);
+    let history = AgentHistory::with_store(MemoryStore::new(), Some("both"))
+        .await
+        .unwrap();
+    let mut agent =
+        SmartAgent::new(AgentBuilder::new(FailingModel).build(), history)
+            .with_retry_policy(fast.clone())
+            .with_fallback(model.agent());
+    assert_eq!(agent.chat("hello").await.unwrap(), "recovered");
+    assert_eq!(model.calls(), 2);
+
+    let reply = agent.history().recent(1).await.unwrap().remove(0);
+    assert_eq!(reply.metadata["retries"], json!(3));
+    assert_eq!(reply.metadata["fallback"], json!(true));
+    let attempts = reply.metadata["attempts"].as_array().unwrap();
+    assert_eq!(attempts.len(), 5);
+    assert_eq!(attempts[4]["agent"], json!("fallback"));
+    assert_eq!(attempts[4]["attempt"], json!(2));
+    assert_eq!(attempts[4]["success"], json!(true));
+
+    // Errors the policy doesn't consider retryable fail immediately
+    let model = FlakyModel::new(1);
+    let history = AgentHistory::with_store(MemoryStore::new(), Some("no"))
+        .await
+        .unwrap();
+    let mut agent = SmartAgent::new(model.agent(), history)
+        .with_retry_policy(fast.with_retryable(|_| false));
+    assert!(agent.chat("hello").await.is_err());
+    assert_eq!(model.calls(), 1);
+}
+
+#[test]
+fn test_retry_backoff() {
+    let policy = RetryPolicy::default()
+        .with_backoff(std::time::Duration::from_millis(100), 2.0)
+        .with_max_backoff(std::time::Duration::from_millis(500));
+    let delays: Vec<u128> =
+        (1..=5).map(|retry| policy.backoff(retry).as_millis()).collect();
+    assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+    assert_eq!(RetryPolicy::none().max_attempts(), 1);
+}